@@ -1,27 +1,190 @@
-DROP TABLE IF EXISTS ygo_quiz;
-DROP TABLE IF EXISTS mtg_quiz;
-DROP TABLE IF EXISTS quiz;
-DROP TYPE IF EXISTS quiz_type;
+-- クイズは再起動をまたいで保持するので、既存のテーブルは消さない
+-- ただし、user_id を主キーとしていた頃のテーブルが残っていれば作り直す
+DO $$
+BEGIN
+  IF EXISTS (SELECT 1 FROM information_schema.tables WHERE table_name = 'quiz')
+    AND NOT EXISTS (
+      SELECT 1 FROM information_schema.columns WHERE table_name = 'quiz' AND column_name = 'id'
+    ) THEN
+    DROP TABLE IF EXISTS ygo_quiz, mtg_quiz, quiz;
+  END IF;
+END $$;
 
-CREATE TYPE quiz_type AS ENUM ('ygo', 'mtg');
+DO $$
+BEGIN
+  CREATE TYPE quiz_type AS ENUM ('ygo', 'mtg');
+EXCEPTION WHEN duplicate_object THEN NULL;
+END $$;
 
-CREATE TABLE ygo_quiz (
-  user_id BIGINT PRIMARY KEY,
+DO $$
+BEGIN
+  CREATE TYPE quiz_mode AS ENUM ('text', 'flavor');
+EXCEPTION WHEN duplicate_object THEN NULL;
+END $$;
+
+ALTER TYPE quiz_mode ADD VALUE IF NOT EXISTS 'effect';
+ALTER TYPE quiz_mode ADD VALUE IF NOT EXISTS 'partial';
+ALTER TYPE quiz_mode ADD VALUE IF NOT EXISTS 'blank';
+
+DO $$
+BEGIN
+  CREATE TYPE quiz_outcome AS ENUM ('correct', 'giveup', 'timeout');
+EXCEPTION WHEN duplicate_object THEN NULL;
+END $$;
+
+ALTER TYPE quiz_outcome ADD VALUE IF NOT EXISTS 'expired';
+
+CREATE TABLE IF NOT EXISTS quiz (
+  id BIGSERIAL PRIMARY KEY,
+  user_id BIGINT NOT NULL,
+  quiz_type quiz_type NOT NULL,
+  quiz_mode quiz_mode NOT NULL DEFAULT 'text',
+  attempts INT NOT NULL DEFAULT 0,
+  hints INT NOT NULL DEFAULT 0,
+  channel_id BIGINT NOT NULL,
+  deadline TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS quiz_user_id_idx ON quiz (user_id);
+
+ALTER TABLE quiz ADD COLUMN IF NOT EXISTS created_at TIMESTAMPTZ NOT NULL DEFAULT now();
+ALTER TABLE quiz ADD COLUMN IF NOT EXISTS guild_id BIGINT;
+ALTER TABLE quiz ADD COLUMN IF NOT EXISTS daily_date DATE;
+-- 早押しクイズは user_id を持たない
+ALTER TABLE quiz ALTER COLUMN user_id DROP NOT NULL;
+-- 出題や回答を本人にだけ表示するクイズ
+ALTER TABLE quiz ADD COLUMN IF NOT EXISTS private BOOLEAN NOT NULL DEFAULT false;
+-- ボタンを無効にするため、出題メッセージの場所を覚えておく
+ALTER TABLE quiz ADD COLUMN IF NOT EXISTS prompt_message_id BIGINT;
+ALTER TABLE quiz ADD COLUMN IF NOT EXISTS prompt_token TEXT;
+-- クイズ用に作ったスレッド
+ALTER TABLE quiz ADD COLUMN IF NOT EXISTS thread_id BIGINT;
+-- テキストを少しずつ見せる出題で、テキストを分けた数（他の出題形式では NULL）
+ALTER TABLE quiz ADD COLUMN IF NOT EXISTS segments INT;
+ALTER TABLE quiz ADD COLUMN IF NOT EXISTS text_as_image BOOLEAN NOT NULL DEFAULT false;
+-- 出題時にカード名を伏せたか。途中で設定が変わっても、続きのテキストは同じように伏せる
+ALTER TABLE quiz ADD COLUMN IF NOT EXISTS mask_card_name BOOLEAN NOT NULL DEFAULT true;
+-- 穴埋めの出題で、空欄に入る言葉（他の出題形式では NULL）
+ALTER TABLE quiz ADD COLUMN IF NOT EXISTS blank_answer TEXT;
+
+CREATE TABLE IF NOT EXISTS ygo_quiz (
+  quiz_id BIGINT PRIMARY KEY,
   konami_id BIGINT NOT NULL,
   card_name TEXT NOT NULL,
   card_name_ruby TEXT NOT NULL,
-  card_text TEXT NOT NULL
+  card_text TEXT NOT NULL,
+  image_url TEXT NOT NULL
 );
 
-CREATE TABLE mtg_quiz (
-  user_id BIGINT PRIMARY KEY,
+CREATE TABLE IF NOT EXISTS mtg_quiz (
+  quiz_id BIGINT PRIMARY KEY,
   scryfall_uri TEXT NOT NULL,
   card_name TEXT NOT NULL,
   english_name TEXT NOT NULL,
-  card_text TEXT NOT NULL
+  card_text TEXT NOT NULL,
+  flavor_text TEXT
+);
+
+ALTER TABLE mtg_quiz ADD COLUMN IF NOT EXISTS image_uri TEXT;
+-- 効果ごとに分けたカードテキスト (ygo::text::CardText)。以前のクイズは NULL
+ALTER TABLE ygo_quiz ADD COLUMN IF NOT EXISTS structured_text JSONB;
+-- 誤答やヒントのたびにイラストを取得し直さないよう、出題時に保存しておく
+ALTER TABLE ygo_quiz ADD COLUMN IF NOT EXISTS art BYTEA;
+
+CREATE TABLE IF NOT EXISTS quiz_result (
+  id BIGSERIAL PRIMARY KEY,
+  user_id BIGINT NOT NULL,
+  quiz_type quiz_type NOT NULL,
+  outcome quiz_outcome NOT NULL,
+  attempts INT NOT NULL,
+  hints INT NOT NULL,
+  finished_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- 誰も正解しなかった早押しクイズの結果は user_id を持たない
+ALTER TABLE quiz_result ALTER COLUMN user_id DROP NOT NULL;
+-- 成績はサーバーごとに集計する（DM での結果は NULL）
+ALTER TABLE quiz_result ADD COLUMN IF NOT EXISTS guild_id BIGINT;
+-- テキストを少しずつ見せる出題の得点（100 点満点、他の出題形式では NULL）
+ALTER TABLE quiz_result ADD COLUMN IF NOT EXISTS score INT;
+
+-- サーバーごと・ゲームごとの「今日の一枚」（日付は日本時間）
+CREATE TABLE IF NOT EXISTS daily_card (
+  guild_id BIGINT NOT NULL,
+  quiz_type quiz_type NOT NULL,
+  quiz_date DATE NOT NULL,
+  card JSONB NOT NULL,
+  PRIMARY KEY (guild_id, quiz_type, quiz_date)
+);
+
+-- 「今日の一枚」への挑戦。solved が NULL の間は挑戦中
+CREATE TABLE IF NOT EXISTS daily_entry (
+  guild_id BIGINT NOT NULL,
+  quiz_type quiz_type NOT NULL,
+  quiz_date DATE NOT NULL,
+  user_id BIGINT NOT NULL,
+  solved BOOLEAN,
+  attempts INT,
+  hints INT,
+  PRIMARY KEY (guild_id, quiz_type, quiz_date, user_id)
+);
+
+-- 早押しクイズの定期投稿。cron は日本時間で解釈する
+CREATE TABLE IF NOT EXISTS quiz_schedule (
+  id BIGSERIAL PRIMARY KEY,
+  guild_id BIGINT NOT NULL,
+  channel_id BIGINT NOT NULL,
+  cron TEXT NOT NULL,
+  quiz_type quiz_type NOT NULL,
+  quiz_mode quiz_mode NOT NULL DEFAULT 'text',
+  fname TEXT,
+  mtg_format TEXT,
+  rare BOOLEAN NOT NULL DEFAULT false,
+  time_limit BIGINT,
+  last_run_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- サーバーごとの設定。行がなければ既定値を使う
+CREATE TABLE IF NOT EXISTS guild_settings (
+  guild_id BIGINT PRIMARY KEY,
+  mtg_format TEXT NOT NULL DEFAULT 'eternal',
+  mask_card_name BOOLEAN NOT NULL DEFAULT true,
+  time_limit BIGINT,
+  ygo_enabled BOOLEAN NOT NULL DEFAULT true,
+  mtg_enabled BOOLEAN NOT NULL DEFAULT true,
+  accept_alternate_name BOOLEAN NOT NULL DEFAULT true
+);
+
+-- `/quiz` を使えるチャンネル。サーバーごとに一つもなければ全てのチャンネルで使える
+CREATE TABLE IF NOT EXISTS quiz_channel (
+  guild_id BIGINT NOT NULL,
+  channel_id BIGINT NOT NULL,
+  PRIMARY KEY (guild_id, channel_id)
 );
 
-CREATE TABLE quiz (
+-- ユーザーごとの設定。行がなければ既定値を使う
+CREATE TABLE IF NOT EXISTS user_preferences (
   user_id BIGINT PRIMARY KEY,
-  quiz_type quiz_type
+  private BOOLEAN NOT NULL DEFAULT false
+);
+
+DO $$
+BEGIN
+  CREATE TYPE quiz_concurrency AS ENUM ('single', 'per_game', 'per_channel');
+EXCEPTION WHEN duplicate_object THEN NULL;
+END $$;
+
+-- 同時に持てるクイズの数
+ALTER TABLE user_preferences ADD COLUMN IF NOT EXISTS concurrency quiz_concurrency NOT NULL DEFAULT 'single';
+
+-- 出題に使えなかったカード。しばらくの間は引いても使わずに引き直す
+CREATE TABLE IF NOT EXISTS skipped_card (
+  quiz_type quiz_type NOT NULL,
+  card_id TEXT NOT NULL,
+  reason TEXT NOT NULL,
+  skipped_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+  PRIMARY KEY (quiz_type, card_id)
 );
+-- 出題形式によっては使えるカードを記録していたので、その記録は消す
+DELETE FROM skipped_card
+WHERE quiz_type = 'mtg' AND reason IN ('日本語のテキストがありません', 'フレーバーテキストがありません');
@@ -0,0 +1,58 @@
+use serenity::{all::User, client::Context};
+
+use crate::db::{Quiz, QuizType};
+use crate::reply::Reply;
+use crate::{mtg, ygo, Bot};
+
+/// 出題中のクイズに対する操作。スラッシュコマンドからもボタンからも同じ処理を通す
+pub(crate) enum Action {
+    /// カード名での回答
+    Answer(String),
+    Hint,
+    Giveup,
+}
+
+pub(crate) async fn run(
+    bot: &Bot,
+    ctx: &Context,
+    user: &User,
+    quiz: &Quiz,
+    action: Action,
+) -> Result<Reply, String> {
+    match (action, quiz.quiz_type) {
+        // 早押しクイズは他の人も挑戦中なので、一人では諦められない
+        (Action::Giveup, _) if quiz.user_id.is_none() => {
+            Ok(Reply::text("早押しクイズはギブアップできません"))
+        }
+        (Action::Answer(answer), _) => self::answer(bot, ctx, user, quiz, &answer)
+            .await
+            .map(|(reply, _)| reply),
+        (Action::Hint, QuizType::Ygo) => ygo::command_hint(bot, ctx, user, quiz).await,
+        (Action::Hint, QuizType::Mtg) => mtg::command_hint(bot, ctx, user, quiz).await,
+        (Action::Giveup, QuizType::Ygo) => ygo::command_giveup(bot, ctx, user, quiz).await,
+        (Action::Giveup, QuizType::Mtg) => mtg::command_giveup(bot, ctx, user, quiz).await,
+    }
+}
+
+/// カード名での回答。この回答でクイズが終わった（正解した）かどうかも返す
+/// 同時に正解した人がいても、勝ちになるのは 1 人だけ
+pub(crate) async fn answer(
+    bot: &Bot,
+    ctx: &Context,
+    user: &User,
+    quiz: &Quiz,
+    answer: &str,
+) -> Result<(Reply, bool), String> {
+    match quiz.quiz_type {
+        QuizType::Ygo => ygo::command_ans(bot, ctx, user, answer, quiz).await,
+        QuizType::Mtg => mtg::command_ans(bot, ctx, user, answer, quiz).await,
+    }
+}
+
+/// 回答が正解かどうかだけを確かめる。誤答は記録しない
+pub(crate) async fn check_answer(bot: &Bot, quiz: &Quiz, answer: &str) -> Result<bool, String> {
+    match quiz.quiz_type {
+        QuizType::Ygo => ygo::check_answer(bot, quiz, answer).await,
+        QuizType::Mtg => mtg::check_answer(bot, quiz, answer).await,
+    }
+}
@@ -0,0 +1,72 @@
+use serenity::{
+    all::{CommandDataOptionValue, CommandInteraction, Permissions},
+    builder::{CreateCommand, CreateCommandOption},
+    client::Context,
+};
+use tracing::info;
+
+use crate::reply::{Deferred, Reply};
+use crate::{channels, schedule, settings, Bot};
+
+/// サーバー管理者向けの `/quizadmin` コマンド
+pub(crate) fn create_command() -> CreateCommand {
+    CreateCommand::new("quizadmin")
+        .description("Manage quiz bot for this server")
+        .default_member_permissions(Permissions::MANAGE_GUILD)
+        .dm_permission(false)
+        .add_option(schedule::create_subcommand(CreateCommandOption::new(
+            serenity::all::CommandOptionType::SubCommandGroup,
+            "schedule",
+            "",
+        )))
+        .add_option(settings::create_subcommand(CreateCommandOption::new(
+            serenity::all::CommandOptionType::SubCommandGroup,
+            "settings",
+            "",
+        )))
+        .add_option(channels::create_subcommand(CreateCommandOption::new(
+            serenity::all::CommandOptionType::SubCommandGroup,
+            "channels",
+            "",
+        )))
+}
+
+pub(crate) async fn receive_command(bot: &Bot, ctx: &Context, command: CommandInteraction) {
+    let Some(deferred) = Deferred::start(&ctx.http, command.id, &command.token, true).await else {
+        return;
+    };
+
+    let CommandDataOptionValue::SubCommandGroup(after_group) = &command.data.options[0].value
+    else {
+        //unreachable
+        panic!()
+    };
+    let group = command.data.options[0].name.as_str();
+
+    // コマンドの権限設定はサーバー側で変えられるので、ここでも確認する
+    let is_manager = command
+        .member
+        .as_ref()
+        .and_then(|member| member.permissions)
+        .is_some_and(|permissions| permissions.manage_guild());
+
+    info!(group);
+    let result = match (command.guild_id, is_manager, group) {
+        (None, _, _) => Err("サーバー内で実行してください".to_owned()),
+        (_, false, _) => {
+            Err("このコマンドは「サーバー管理」権限を持つメンバーのみ使えます".to_owned())
+        }
+        (Some(guild_id), true, "schedule") => {
+            schedule::receive_command(bot, guild_id.into(), &after_group[0]).await
+        }
+        (Some(guild_id), true, "settings") => {
+            settings::receive_command(bot, guild_id.into(), &after_group[0]).await
+        }
+        (Some(guild_id), true, "channels") => {
+            channels::receive_command(bot, guild_id.into(), &after_group[0]).await
+        }
+        _ => Err(format!("Unknown Command: {}", group)),
+    };
+
+    deferred.respond(result.map(Reply::text)).await;
+}
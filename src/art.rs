@@ -0,0 +1,99 @@
+use std::io::Cursor;
+
+use image::{imageops::FilterType, ImageFormat};
+
+/// 段階ごとのモザイクの細かさ（短辺あたりのブロック数）
+/// これを使い切ったら元の画像をそのまま出す
+const MOSAIC_BLOCKS: [u32; 4] = [6, 12, 24, 48];
+
+/// イラストを `level` 段階目のモザイクにかけ、PNG として返す
+pub(crate) fn obscure_art(bytes: &[u8], level: usize) -> Result<Vec<u8>, String> {
+    let img = image::load_from_memory(bytes).map_err(|e| e.to_string())?;
+
+    let img = match MOSAIC_BLOCKS.get(level) {
+        Some(blocks) => {
+            let (width, height) = (img.width(), img.height());
+            let block_size = (width.min(height) / blocks).max(1);
+            img.resize_exact(
+                (width / block_size).max(1),
+                (height / block_size).max(1),
+                FilterType::Triangle,
+            )
+            .resize_exact(width, height, FilterType::Nearest)
+        }
+        None => img,
+    };
+
+    let mut png = Cursor::new(Vec::new());
+    img.write_to(&mut png, ImageFormat::Png)
+        .map_err(|e| e.to_string())?;
+
+    Ok(png.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use image::{Rgb, RgbImage};
+
+    use super::*;
+
+    fn encode(img: RgbImage) -> Vec<u8> {
+        let mut png = Cursor::new(Vec::new());
+        img.write_to(&mut png, ImageFormat::Png).unwrap();
+        png.into_inner()
+    }
+
+    /// 隣り合う画素の色が全て異なる画像
+    fn art() -> Vec<u8> {
+        encode(RgbImage::from_fn(96, 64, |x, y| {
+            Rgb([(x * 2) as u8, (y * 3) as u8, (x * y % 256) as u8])
+        }))
+    }
+
+    fn colours(png: &[u8]) -> usize {
+        let img = image::load_from_memory(png).unwrap().to_rgb8();
+        img.pixels().collect::<HashSet<_>>().len()
+    }
+
+    #[test]
+    fn sharpen_level_by_level() {
+        let art = art();
+        let levels = (0..MOSAIC_BLOCKS.len())
+            .map(|level| obscure_art(&art, level).unwrap())
+            .collect::<Vec<_>>();
+
+        for png in &levels {
+            let img = image::load_from_memory(png).unwrap();
+            assert_eq!((img.width(), img.height()), (96, 64));
+        }
+        // 最初は 6 × 9 ブロック程度のモザイク
+        assert!(colours(&levels[0]) <= 6 * 9, "{}", colours(&levels[0]));
+        for pair in levels.windows(2) {
+            assert!(colours(&pair[0]) < colours(&pair[1]));
+        }
+    }
+
+    #[test]
+    fn reveal_the_original_after_the_last_level() {
+        let art = art();
+        let original = image::load_from_memory(&art).unwrap().to_rgb8();
+        let revealed = obscure_art(&art, MOSAIC_BLOCKS.len()).unwrap();
+
+        assert_eq!(
+            image::load_from_memory(&revealed).unwrap().to_rgb8(),
+            original
+        );
+        assert_eq!(obscure_art(&art, usize::MAX).unwrap(), revealed);
+    }
+
+    #[test]
+    fn handle_tiny_and_broken_images() {
+        let tiny = encode(RgbImage::from_pixel(3, 2, Rgb([0, 0, 0])));
+        let img = image::load_from_memory(&obscure_art(&tiny, 0).unwrap()).unwrap();
+        assert_eq!((img.width(), img.height()), (3, 2));
+
+        assert!(obscure_art(b"not an image", 0).is_err());
+    }
+}
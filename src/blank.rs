@@ -0,0 +1,285 @@
+//! 穴埋めの出題で、カードテキストから空欄にする言葉を選ぶ
+
+use rand::seq::SliceRandom;
+
+use crate::common::roughly_card_name_equal;
+use crate::db::QuizType;
+
+/// 空欄の表示
+pub(crate) const PLACEHOLDER: &str = "［　？　］";
+
+pub(crate) const NO_BLANK: &str = "カードテキストに空欄にできる言葉がありません";
+
+/// 遊戯王のテキストで空欄にする言葉。長いものを先に照合するので、順不同でよい
+const YGO_KEYWORDS: [&str; 40] = [
+    "除外",
+    "破壊",
+    "墓地",
+    "手札",
+    "デッキ",
+    "エクストラデッキ",
+    "フィールド",
+    "特殊召喚",
+    "通常召喚",
+    "アドバンス召喚",
+    "融合召喚",
+    "シンクロ召喚",
+    "エクシーズ召喚",
+    "リンク召喚",
+    "儀式召喚",
+    "ペンデュラム召喚",
+    "リリース",
+    "裏側表示",
+    "表側表示",
+    "守備表示",
+    "攻撃表示",
+    "対象",
+    "無効",
+    "ドロー",
+    "攻撃力",
+    "守備力",
+    "戦闘ダメージ",
+    "効果ダメージ",
+    "貫通",
+    "チューナー",
+    "エクシーズ素材",
+    "装備",
+    "コントロール",
+    "バウンス",
+    "エンドフェイズ",
+    "スタンバイフェイズ",
+    "メインフェイズ",
+    "バトルフェイズ",
+    "ダメージステップ",
+    "チェーン",
+];
+
+/// Magic のテキストで空欄にする言葉（主にキーワード能力）
+const MTG_KEYWORDS: [&str; 40] = [
+    "飛行",
+    "到達",
+    "先制攻撃",
+    "二段攻撃",
+    "接死",
+    "絆魂",
+    "速攻",
+    "警戒",
+    "トランプル",
+    "呪禁",
+    "被覆",
+    "破壊不能",
+    "瞬速",
+    "護法",
+    "威迫",
+    "防衛",
+    "果敢",
+    "占術",
+    "諜報",
+    "切削",
+    "宝物",
+    "手掛かり",
+    "食物",
+    "追放",
+    "生け贄",
+    "タップ",
+    "アンタップ",
+    "墓地",
+    "ライブラリー",
+    "手札",
+    "戦場",
+    "打ち消す",
+    "カウンター",
+    "トークン",
+    "ライフ",
+    "マナ",
+    "プレインズウォーカー",
+    "クリーチャー",
+    "エンチャント",
+    "アーティファクト",
+];
+
+/// 一部を空欄にしたテキストと、空欄に入る言葉
+#[derive(PartialEq, Debug)]
+pub(crate) struct Blank {
+    pub text: String,
+    pub answer: String,
+}
+
+/// 空欄にする言葉をランダムに選ぶ。空欄にできる言葉がなければ `None`
+pub(crate) fn choose(text: &str, quiz_type: QuizType) -> Option<Blank> {
+    let candidates = candidates(text, keywords(quiz_type));
+    let (_, answer) = candidates.choose(&mut rand::thread_rng())?;
+
+    Some(blank_out(text, &candidates, answer))
+}
+
+/// 回答が空欄に入る言葉と一致するか。カード名と同じく、全角と半角やひらがなとカタカナの違いは許す
+pub(crate) fn is_correct(input: &str, answer: &str) -> bool {
+    roughly_card_name_equal(input.trim(), answer, None)
+}
+
+/// ヒントのたびに、空欄に入る言葉を頭から 1 文字ずつ見せる。最後の 1 文字は見せない
+/// `level` は `Quiz::reveal_level`
+pub(crate) fn hint(answer: &str, level: usize) -> String {
+    let chars = answer.chars().collect::<Vec<_>>();
+    let shown = level.saturating_sub(1).min(chars.len().saturating_sub(1));
+    if shown == 0 {
+        format!("ヒント：空欄に入るのは {} 文字の言葉です", chars.len())
+    } else {
+        format!(
+            "ヒント：空欄に入るのは「{}」から始まる {} 文字の言葉です",
+            chars[..shown].iter().collect::<String>(),
+            chars.len()
+        )
+    }
+}
+
+fn keywords(quiz_type: QuizType) -> &'static [&'static str] {
+    match quiz_type {
+        QuizType::Ygo => &YGO_KEYWORDS,
+        QuizType::Mtg => &MTG_KEYWORDS,
+    }
+}
+
+fn is_digit(c: char) -> bool {
+    c.is_ascii_digit() || ('０'..='９').contains(&c)
+}
+
+/// 空欄にできる言葉（キーワードと数）の位置
+/// 「」の中はカード名なので、{} の中はマナ・シンボルなので空欄にしない
+fn candidates<'a>(text: &'a str, keywords: &[&str]) -> Vec<(usize, &'a str)> {
+    let mut candidates = vec![];
+    let mut quoted = 0usize;
+    let mut in_symbol = false;
+    let mut rest_from = 0;
+    for (i, c) in text.char_indices() {
+        // 直前に選んだ言葉の途中
+        if i < rest_from {
+            continue;
+        }
+        match c {
+            '「' => quoted += 1,
+            '」' => quoted = quoted.saturating_sub(1),
+            '{' => in_symbol = true,
+            '}' => in_symbol = false,
+            _ => {}
+        }
+        if quoted > 0 || in_symbol {
+            continue;
+        }
+
+        let rest = &text[i..];
+        let word = if is_digit(c) {
+            rest.find(|c| !is_digit(c)).map_or(rest, |end| &rest[..end])
+        } else {
+            match keywords
+                .iter()
+                .filter(|keyword| rest.starts_with(**keyword))
+                .max_by_key(|keyword| keyword.len())
+            {
+                Some(keyword) => &rest[..keyword.len()],
+                None => continue,
+            }
+        };
+        candidates.push((i, word));
+        rest_from = i + word.len();
+    }
+
+    candidates
+}
+
+/// `answer` と同じ言葉は全て空欄にする。他の箇所から答えが分からないように
+fn blank_out(text: &str, candidates: &[(usize, &str)], answer: &str) -> Blank {
+    let mut blanked = String::new();
+    let mut last = 0;
+    for (start, word) in candidates.iter().filter(|(_, word)| *word == answer) {
+        blanked.push_str(&text[last..*start]);
+        blanked.push_str(PLACEHOLDER);
+        last = start + word.len();
+    }
+    blanked.push_str(&text[last..]);
+
+    Blank {
+        text: blanked,
+        answer: answer.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(text: &str, quiz_type: QuizType) -> Vec<&str> {
+        candidates(text, keywords(quiz_type))
+            .into_iter()
+            .map(|(_, word)| word)
+            .collect()
+    }
+
+    #[test]
+    fn find_keywords_and_numbers() {
+        assert_eq!(
+            words(
+                "①：１０００ＬＰを払って発動できる。デッキから「ブラック・マジシャン」１体を特殊召喚する。",
+                QuizType::Ygo
+            ),
+            vec!["１０００", "デッキ", "１", "特殊召喚"]
+        );
+        // マナ・シンボルの中の数は空欄にしない
+        assert_eq!(
+            words(
+                "飛行\n{2}{W}：クリーチャー1体を対象とする。それを追放する。",
+                QuizType::Mtg
+            ),
+            vec!["飛行", "クリーチャー", "1", "追放"]
+        );
+    }
+
+    #[test]
+    fn prefer_longer_keywords() {
+        assert_eq!(
+            words("エクストラデッキからデッキに戻す。", QuizType::Ygo),
+            vec!["エクストラデッキ", "デッキ"]
+        );
+    }
+
+    #[test]
+    fn blank_every_occurrence() {
+        let text = "相手の墓地のカード１枚を除外する。その後、自分の墓地のカードを除外する。";
+        let candidates = candidates(text, keywords(QuizType::Ygo));
+
+        assert_eq!(
+            blank_out(text, &candidates, "除外"),
+            Blank {
+                text: "相手の墓地のカード１枚を［　？　］する。その後、自分の墓地のカードを［　？　］する。"
+                    .to_owned(),
+                answer: "除外".to_owned(),
+            }
+        );
+        assert!(choose("「青眼の白龍」として扱う。", QuizType::Ygo).is_none());
+    }
+
+    #[test]
+    fn accept_loose_answers() {
+        assert!(is_correct("1000", "１０００"));
+        assert!(is_correct(" とらんぷる ", "トランプル"));
+        assert!(!is_correct("破壊", "除外"));
+    }
+
+    #[test]
+    fn reveal_the_answer_little_by_little() {
+        assert_eq!(
+            hint("トランプル", 1),
+            "ヒント：空欄に入るのは 5 文字の言葉です"
+        );
+        assert_eq!(
+            hint("トランプル", 3),
+            "ヒント：空欄に入るのは「トラ」から始まる 5 文字の言葉です"
+        );
+        // 最後の 1 文字は見せない
+        assert_eq!(
+            hint("飛行", 9),
+            "ヒント：空欄に入るのは「飛」から始まる 2 文字の言葉です"
+        );
+    }
+}
@@ -0,0 +1,311 @@
+use chrono::{TimeDelta, Utc};
+use serenity::{
+    all::{
+        ActionRowComponent, Builder, ButtonStyle, ChannelId, ComponentInteraction, CreateActionRow,
+        CreateButton, CreateInputText, CreateInteractionResponse,
+        CreateInteractionResponseFollowup, CreateInteractionResponseMessage, CreateModal,
+        EditInteractionResponse, EditMessage, Http, InputTextStyle, InteractionId, Message,
+        MessageId, ModalInteraction, User,
+    },
+    client::Context,
+};
+use tracing::{error, info};
+
+use crate::action::{self, Action};
+use crate::common::QUIZ_ENDED;
+use crate::db::{get_quiz_by_id, set_prompt, Quiz, QuizMode, QuizType};
+use crate::reply::Deferred;
+use crate::Bot;
+
+/// インタラクションのトークンで応答を編集できる期間
+const TOKEN_LIFETIME: TimeDelta = TimeDelta::minutes(15);
+
+/// 出題メッセージの下に付けるボタン
+/// ヒントは遊戯王と、Magic ではテキストを少しずつ見せる出題と穴埋めだけ、ギブアップは自分のクイズだけで使える
+pub(crate) fn quiz_buttons(quiz: &Quiz, disabled: bool) -> CreateActionRow {
+    let mut buttons = vec![CreateButton::new(format!("quiz:answer:{}", quiz.id))
+        .label("回答する")
+        .style(ButtonStyle::Primary)
+        .disabled(disabled)];
+    if quiz.quiz_type == QuizType::Ygo
+        || matches!(quiz.quiz_mode, QuizMode::Partial | QuizMode::Blank)
+    {
+        buttons.push(
+            CreateButton::new(format!("quiz:hint:{}", quiz.id))
+                .label("ヒント")
+                .style(ButtonStyle::Secondary)
+                .disabled(disabled),
+        );
+    }
+    if quiz.user_id.is_some() {
+        buttons.push(
+            CreateButton::new(format!("quiz:giveup:{}", quiz.id))
+                .label("ギブアップ")
+                .style(ButtonStyle::Danger)
+                .disabled(disabled),
+        );
+    }
+
+    CreateActionRow::Buttons(buttons)
+}
+
+/// インタラクションへの応答として送った出題文の場所を、あとでボタンを無効にできるよう記録する
+/// `thread` を指定した場合は、出題文からクイズ用のスレッドを作る
+pub(crate) async fn prompt_sent(
+    bot: &Bot,
+    http: &Http,
+    token: &str,
+    quiz: &Quiz,
+    prompt: &Message,
+    thread: bool,
+) {
+    let result = match set_prompt(&bot.database, &quiz.id, &prompt.id.into(), Some(token))
+        .await
+        .map_err(|e| e.to_string())
+    {
+        Ok(()) if thread => crate::thread::open(bot, http, quiz, prompt).await,
+        result => result,
+    };
+    if let Err(err) = result {
+        error!("{}", err)
+    }
+}
+
+/// 終了したクイズの出題メッセージのボタンを無効にする
+pub(crate) async fn disable_buttons(http: &Http, quiz: &Quiz) {
+    let components = vec![quiz_buttons(quiz, true)];
+    let result = match (&quiz.prompt_token, quiz.prompt_message_id) {
+        // 本人にだけ表示したメッセージは、トークンでしか編集できない
+        (Some(token), _) if Utc::now() - quiz.created_at < TOKEN_LIFETIME => {
+            EditInteractionResponse::new()
+                .components(components)
+                .execute(http, token)
+                .await
+        }
+        (_, Some(message_id)) if !quiz.private => {
+            ChannelId::new(quiz.channel_id as u64)
+                .edit_message(
+                    http,
+                    MessageId::new(message_id as u64),
+                    EditMessage::new().components(components),
+                )
+                .await
+        }
+        _ => return,
+    };
+
+    if let Err(err) = result {
+        error!("Failed to disable buttons of quiz {}: {}", quiz.id, err);
+    }
+}
+
+/// 発表をせずに終わったクイズ（放置されたものや置き換えられたもの）の後始末
+/// ボタンを無効にし、クイズ用のスレッドをアーカイブする
+pub(crate) async fn retire(http: &Http, quiz: &Quiz) {
+    disable_buttons(http, quiz).await;
+    if let Some(thread_id) = quiz.thread_id {
+        crate::thread::archive(http, thread_id).await;
+    }
+}
+
+/// `quiz:<操作>:<クイズ ID>` の形の custom_id を分解する
+fn parse_custom_id(custom_id: &str) -> Option<(&str, i64)> {
+    let mut parts = custom_id.split(':');
+    let (Some("quiz"), Some(action), Some(quiz_id)) = (parts.next(), parts.next(), parts.next())
+    else {
+        return None;
+    };
+
+    Some((action, quiz_id.parse().ok()?))
+}
+
+pub(crate) async fn receive_component(bot: &Bot, ctx: &Context, component: ComponentInteraction) {
+    let Some((action, quiz_id)) = parse_custom_id(&component.data.custom_id) else {
+        return;
+    };
+    let action = match action {
+        "answer" => None,
+        "hint" => Some(Action::Hint),
+        "giveup" => Some(Action::Giveup),
+        _ => return,
+    };
+    info!(
+        "Button {} pressed by {}",
+        component.data.custom_id, component.user
+    );
+
+    let Some(quiz) = find_target(
+        bot,
+        ctx,
+        component.id,
+        &component.token,
+        &component.user,
+        quiz_id,
+    )
+    .await
+    else {
+        return;
+    };
+
+    match action {
+        Some(action) => {
+            run_action(
+                bot,
+                ctx,
+                component.id,
+                &component.token,
+                &component.user,
+                &quiz,
+                action,
+            )
+            .await
+        }
+        // 回答はモーダルで入力してもらう
+        None => {
+            let modal = CreateModal::new(format!("quiz:answer:{}", quiz.id), "回答する")
+                .components(vec![CreateActionRow::InputText(
+                    CreateInputText::new(
+                        InputTextStyle::Short,
+                        match quiz.quiz_mode {
+                            QuizMode::Blank => "空欄に入る言葉",
+                            _ => "カード名",
+                        },
+                        "answer",
+                    )
+                    .required(true),
+                )]);
+            if let Err(err) = component
+                .create_response(&ctx.http, CreateInteractionResponse::Modal(modal))
+                .await
+            {
+                error!("{}", err)
+            }
+        }
+    }
+}
+
+pub(crate) async fn receive_modal(bot: &Bot, ctx: &Context, modal: ModalInteraction) {
+    let Some(("answer", quiz_id)) = parse_custom_id(&modal.data.custom_id) else {
+        return;
+    };
+    let Some(answer) = modal
+        .data
+        .components
+        .iter()
+        .flat_map(|row| &row.components)
+        .find_map(|component| match component {
+            ActionRowComponent::InputText(input) if input.custom_id == "answer" => {
+                input.value.clone()
+            }
+            _ => None,
+        })
+    else {
+        return;
+    };
+    info!("Modal {} submitted by {}", modal.data.custom_id, modal.user);
+
+    let Some(quiz) = find_target(bot, ctx, modal.id, &modal.token, &modal.user, quiz_id).await
+    else {
+        return;
+    };
+
+    run_action(
+        bot,
+        ctx,
+        modal.id,
+        &modal.token,
+        &modal.user,
+        &quiz,
+        Action::Answer(answer),
+    )
+    .await
+}
+
+/// ボタンやモーダルの対象のクイズを探す
+/// 操作できないクイズだった場合は、その旨を応答して `None` を返す
+async fn find_target(
+    bot: &Bot,
+    ctx: &Context,
+    interaction_id: InteractionId,
+    token: &str,
+    user: &User,
+    quiz_id: i64,
+) -> Option<Quiz> {
+    let quiz = match get_quiz_by_id(&bot.database, &quiz_id).await {
+        Ok(quiz) => quiz,
+        Err(sqlx::Error::RowNotFound) => {
+            // 既に終わったクイズのボタンは外しておく
+            let result = CreateInteractionResponse::UpdateMessage(
+                CreateInteractionResponseMessage::new().components(vec![]),
+            )
+            .execute(&ctx.http, (interaction_id, token))
+            .await;
+            let result = match result {
+                Ok(_) => CreateInteractionResponseFollowup::new()
+                    .content(QUIZ_ENDED)
+                    .ephemeral(true)
+                    .execute(&ctx.http, (None, token))
+                    .await
+                    .map(|_| ()),
+                Err(err) => Err(err),
+            };
+            if let Err(err) = result {
+                error!("{}", err)
+            }
+            return None;
+        }
+        Err(err) => {
+            respond_ephemeral(
+                ctx,
+                interaction_id,
+                token,
+                format!("データベースでエラーが発生しました：{}", err),
+            )
+            .await;
+            return None;
+        }
+    };
+
+    if quiz
+        .user_id
+        .is_some_and(|user_id| user_id != i64::from(user.id))
+    {
+        respond_ephemeral(ctx, interaction_id, token, "他の人のクイズです".to_owned()).await;
+        return None;
+    }
+
+    Some(quiz)
+}
+
+async fn run_action(
+    bot: &Bot,
+    ctx: &Context,
+    interaction_id: InteractionId,
+    token: &str,
+    user: &User,
+    quiz: &Quiz,
+    action: Action,
+) {
+    let ephemeral = quiz.private || quiz.daily_date.is_some();
+    let Some(deferred) = Deferred::start(&ctx.http, interaction_id, token, ephemeral).await else {
+        return;
+    };
+
+    deferred
+        .respond(action::run(bot, ctx, user, quiz, action).await)
+        .await;
+    crate::thread::archive_if_finished(bot, &ctx.http, quiz).await;
+}
+
+async fn respond_ephemeral(ctx: &Context, interaction_id: InteractionId, token: &str, msg: String) {
+    if let Err(err) = CreateInteractionResponse::Message(
+        CreateInteractionResponseMessage::new()
+            .content(msg)
+            .ephemeral(true),
+    )
+    .execute(&ctx.http, (interaction_id, token))
+    .await
+    {
+        error!("{}", err)
+    }
+}
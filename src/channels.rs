@@ -0,0 +1,139 @@
+pub mod db;
+
+use serenity::{
+    all::{ChannelType, CommandDataOption, CommandDataOptionValue, CommandInteraction},
+    builder::CreateCommandOption,
+};
+use sqlx::PgPool;
+use tracing::info;
+
+use crate::Bot;
+
+pub(crate) fn create_subcommand(c: CreateCommandOption) -> CreateCommandOption {
+    let channel_option = || {
+        CreateCommandOption::new(
+            serenity::all::CommandOptionType::Channel,
+            "channel",
+            "The channel",
+        )
+        .channel_types(vec![ChannelType::Text])
+        .required(true)
+    };
+
+    c.description("Channels where `/quiz` can be used (all channels if none)")
+        .add_sub_option(
+            CreateCommandOption::new(
+                serenity::all::CommandOptionType::SubCommand,
+                "add",
+                "Allow quizzes in a channel",
+            )
+            .add_sub_option(channel_option()),
+        )
+        .add_sub_option(
+            CreateCommandOption::new(
+                serenity::all::CommandOptionType::SubCommand,
+                "remove",
+                "Disallow quizzes in a channel",
+            )
+            .add_sub_option(channel_option()),
+        )
+        .add_sub_option(CreateCommandOption::new(
+            serenity::all::CommandOptionType::SubCommand,
+            "list",
+            "List channels where quizzes are allowed",
+        ))
+}
+
+pub(crate) async fn receive_command(
+    bot: &Bot,
+    guild_id: i64,
+    subc: &CommandDataOption,
+) -> Result<String, String> {
+    let CommandDataOptionValue::SubCommand(options) = &subc.value else {
+        //unreachable
+        panic!()
+    };
+    let channel_id = options.iter().find_map(|option| match option.value {
+        CommandDataOptionValue::Channel(id) => Some(i64::from(id)),
+        _ => None,
+    });
+
+    info!(subc.name);
+    match (subc.name.as_str(), channel_id) {
+        ("add", Some(channel_id)) => {
+            db::add_channel(&bot.database, &guild_id, &channel_id)
+                .await
+                .map_err(|e| e.to_string())?;
+            Ok(format!("<#{}> でクイズを使えるようにしました", channel_id))
+        }
+        ("remove", Some(channel_id)) => {
+            if db::remove_channel(&bot.database, &guild_id, &channel_id)
+                .await
+                .map_err(|e| e.to_string())?
+            {
+                Ok(format!(
+                    "<#{}> をクイズを使えるチャンネルから外しました",
+                    channel_id
+                ))
+            } else {
+                Ok(format!("<#{}> は登録されていません", channel_id))
+            }
+        }
+        ("list", _) => command_list(bot, guild_id).await,
+        _ => Err(format!("Unknown Command: {}", subc.name)),
+    }
+}
+
+async fn command_list(bot: &Bot, guild_id: i64) -> Result<String, String> {
+    let channels = db::get_channels(&bot.database, &guild_id)
+        .await
+        .map_err(|e| e.to_string())?;
+    if channels.is_empty() {
+        return Ok(
+            "チャンネルは登録されていないので、全てのチャンネルでクイズを使えます".to_owned(),
+        );
+    }
+
+    Ok(format!(
+        "クイズを使えるチャンネル：{}",
+        mention_channels(&channels)
+    ))
+}
+
+fn mention_channels(channels: &[i64]) -> String {
+    channels
+        .iter()
+        .map(|channel_id| format!("<#{}>", channel_id))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// コマンドが実行されたチャンネルでクイズが使えるか確認し、使えなければ断りの文面を返す
+/// スレッドの場合は親チャンネルが登録されていれば使える
+/// データベースを引くので、インタラクションを遅延させてから呼ぶ
+pub(crate) async fn check_command_channel(
+    pool: &PgPool,
+    command: &CommandInteraction,
+) -> Result<(), String> {
+    let Some(guild_id) = command.guild_id.map(i64::from) else {
+        return Ok(());
+    };
+    let db_error = |e: sqlx::Error| format!("データベースでエラーが発生しました：{}", e);
+
+    let mut channel_ids = vec![i64::from(command.channel_id)];
+    if let Some(parent_id) = command.channel.as_ref().and_then(|c| c.parent_id) {
+        channel_ids.push(parent_id.into());
+    }
+    if db::is_allowed(pool, &guild_id, &channel_ids)
+        .await
+        .map_err(db_error)?
+    {
+        return Ok(());
+    }
+
+    let channels = db::get_channels(pool, &guild_id).await.map_err(db_error)?;
+    Err(format!(
+        "このチャンネルではクイズを使えません。次のチャンネルで遊んでください：{}",
+        mention_channels(&channels)
+    ))
+}
@@ -0,0 +1,67 @@
+use sqlx::PgPool;
+
+/// `channel_ids` のどれかでクイズが使えるか。一つも登録していないサーバーでは全てのチャンネルで使える
+pub(crate) async fn is_allowed(
+    pool: &PgPool,
+    guild_id: &i64,
+    channel_ids: &[i64],
+) -> Result<bool, sqlx::Error> {
+    let data: bool = sqlx::query_scalar(
+        r#"
+      SELECT NOT EXISTS (SELECT 1 FROM quiz_channel WHERE guild_id = $1)
+        OR EXISTS (SELECT 1 FROM quiz_channel WHERE guild_id = $1 AND channel_id = ANY($2))
+    "#,
+    )
+    .bind(guild_id)
+    .bind(channel_ids)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(data)
+}
+
+pub(crate) async fn get_channels(pool: &PgPool, guild_id: &i64) -> Result<Vec<i64>, sqlx::Error> {
+    let data: Vec<i64> = sqlx::query_scalar(
+        r#"SELECT channel_id FROM quiz_channel WHERE guild_id = $1 ORDER BY channel_id"#,
+    )
+    .bind(guild_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(data)
+}
+
+pub(crate) async fn add_channel(
+    pool: &PgPool,
+    guild_id: &i64,
+    channel_id: &i64,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+      INSERT INTO quiz_channel (guild_id, channel_id)
+      VALUES ($1, $2)
+      ON CONFLICT DO NOTHING
+    "#,
+    )
+    .bind(guild_id)
+    .bind(channel_id)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// 削除できたかどうかを返す
+pub(crate) async fn remove_channel(
+    pool: &PgPool,
+    guild_id: &i64,
+    channel_id: &i64,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(r#"DELETE FROM quiz_channel WHERE guild_id = $1 AND channel_id = $2"#)
+        .bind(guild_id)
+        .bind(channel_id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
@@ -1,9 +1,210 @@
+use chrono::{FixedOffset, NaiveDate, Utc};
+use serenity::builder::{CreateCommandOption, CreateEmbed, CreateEmbedFooter};
 use tracing::{error, info};
 
+use crate::blank;
+use crate::db::{Quiz, QuizMode, QuizOutcome, QuizParams};
+use crate::text_image;
+
+/// 日付や時刻は日本時間で扱う
+pub(crate) fn jst() -> FixedOffset {
+    FixedOffset::east_opt(9 * 60 * 60).unwrap()
+}
+
+/// 「今日」の日付。日付は日本時間で切り替わる
+pub(crate) fn today() -> NaiveDate {
+    Utc::now().with_timezone(&jst()).date_naive()
+}
+
+/// 各ゲームの `new` に共通する出題形式の選択肢
+pub(crate) fn create_mode_option() -> CreateCommandOption {
+    CreateCommandOption::new(
+        serenity::all::CommandOptionType::String,
+        "mode",
+        "What kind of text will be shown",
+    )
+    .add_string_choice("カードテキスト", "text")
+    .add_string_choice("フレーバーテキスト", "flavor")
+    .add_string_choice("効果を 1 つだけ", "effect")
+    .add_string_choice("効果を 1 つずつ", "partial")
+    .add_string_choice("穴埋め", "blank")
+}
+
+/// ゲームの選択肢
+pub(crate) fn create_game_option(description: &str) -> CreateCommandOption {
+    CreateCommandOption::new(
+        serenity::all::CommandOptionType::String,
+        "game",
+        description,
+    )
+    .add_string_choice("遊戯王", "ygo")
+    .add_string_choice("Magic", "mtg")
+}
+
+/// 各ゲームの `new` に共通する制限時間の指定
+pub(crate) fn create_time_limit_option() -> CreateCommandOption {
+    CreateCommandOption::new(
+        serenity::all::CommandOptionType::Integer,
+        "time_limit",
+        "Time limit in seconds. The answer is revealed when it expires",
+    )
+    .min_int_value(10)
+    .max_int_value(3600)
+}
+
+/// 出題の埋め込みと、同じ内容の文字だけの表現
+/// `subject` は「遊戯王カード」など何を当てるか（穴埋めではカード名）、`filters` は出題範囲の絞り込み
+pub(crate) fn prompt_embed(
+    subject: &str,
+    params: &QuizParams,
+    filters: &[(&str, String)],
+    text: &str,
+) -> (CreateEmbed, String) {
+    let title = if params.quiz_mode == QuizMode::Blank {
+        format!(
+            "「{}」のテキストの{}に入る言葉は？",
+            subject,
+            blank::PLACEHOLDER
+        )
+    } else {
+        format!(
+            "次の{}を持つ{}は？",
+            match params.quiz_mode {
+                QuizMode::Text | QuizMode::Blank => "カードテキスト",
+                QuizMode::Flavor => "フレーバーテキスト",
+                QuizMode::Effect => "効果",
+                QuizMode::Partial => "カードテキストの一部",
+            },
+            subject
+        )
+    };
+    let how_to_answer = format!(
+        "/quiz ans か「回答する」ボタンで回答{}",
+        if let Some(time_limit) = params.time_limit {
+            format!("、制限時間 {} 秒", time_limit)
+        } else {
+            "".to_owned()
+        }
+    );
+
+    // 画像にした場合は、検索できないよう文字では送らない
+    let (embed, text) = if params.text_as_image {
+        (
+            CreateEmbed::new().image(format!("attachment://{}", text_image::FILE_NAME)),
+            "（カードテキストは画像で表示しています）",
+        )
+    } else {
+        (CreateEmbed::new().description(text), text)
+    };
+    let embed = filters.iter().fold(
+        embed
+            .title(&title)
+            .footer(CreateEmbedFooter::new(&how_to_answer)),
+        |embed, (name, value)| embed.field(*name, value, true),
+    );
+    let fallback = format!(
+        "{}({})\n{}\n{}",
+        title,
+        how_to_answer,
+        filters
+            .iter()
+            .map(|(name, value)| format!("{}：{}\n", name, value))
+            .collect::<String>(),
+        text
+    );
+
+    (embed, fallback)
+}
+
+/// 出題に使えないカードを引いた場合に、引き直す回数の上限
+pub(crate) const MAX_DRAWS: usize = 5;
+
+pub(crate) const NO_USABLE_CARD: &str =
+    "出題に使えるカードが見つかりませんでした。もう一度試してください";
+
+/// 他の人が先に正解したり、時間切れになったりして、操作する前にクイズが終わっていた
+pub(crate) const QUIZ_ENDED: &str = "このクイズは既に終了しています";
+
+/// ランダムに引いたカードから出題を用意できなかった理由
+pub(crate) enum DrawError {
+    /// このカードは出題に使えない。記録して別のカードを引き直す
+    Unusable(String),
+    /// 通信の失敗など。引き直しても解決しない
+    Failed(String),
+}
+
+/// 文の終わりとみなす文字
+const SENTENCE_ENDS: [char; 3] = ['。', '！', '？'];
+
+/// 文ごとに分ける。「」や（）の中の句点では分けない
+pub(crate) fn split_sentences(text: &str) -> Vec<String> {
+    let mut sentences = vec![];
+    let mut sentence = String::new();
+    let mut depth = 0usize;
+    for c in text.chars() {
+        match c {
+            '「' | '（' | '(' => depth += 1,
+            '」' | '）' | ')' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+        sentence.push(c);
+        if depth == 0 && (SENTENCE_ENDS.contains(&c) || c == '\n') {
+            sentences.push(std::mem::take(&mut sentence));
+        }
+    }
+    sentences.push(sentence);
+
+    sentences
+        .into_iter()
+        .map(|s| s.trim().to_owned())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+/// テキストを少しずつ見せる出題での区切り
+/// 効果などが 1 つしかなければ、文ごとに分ける
+pub(crate) fn partial_segments(segments: Vec<String>) -> Vec<String> {
+    match segments.as_slice() {
+        [only] => split_sentences(only),
+        _ => segments,
+    }
+}
+
+/// 誤答やヒントのたびに見せる続きのテキストと、その見出し
+/// `level` は `Quiz::reveal_level` で、全て見せ終わっていれば `None`
+pub(crate) fn next_segment(segments: &[String], level: usize) -> Option<(String, &str)> {
+    segments.get(level).map(|segment| {
+        (
+            format!("テキストの続き（{} / {}）", level + 1, segments.len()),
+            segment.as_str(),
+        )
+    })
+}
+
+/// 正解の発表に添える、テキストを少しずつ見せる出題の得点。他の出題形式では空
+/// `quiz` は正解する前の状態のクイズ
+pub(crate) fn score_message(quiz: &Quiz) -> String {
+    match (quiz.segments, quiz.score(QuizOutcome::Correct)) {
+        (Some(segments), Some(score)) => format!(
+            "\n{} / {} 個目のテキストまでで正解：{} 点",
+            (quiz.reveal_level() + 1).min(segments as usize),
+            segments,
+            score
+        ),
+        _ => "".to_owned(),
+    }
+}
+
+/// 問題文中のカード名を伏せる
+pub(crate) fn mask_card_name(text: &str, card_name: &str) -> String {
+    text.replace(card_name, "<カード名>")
+}
+
+/// `alternate_name` は読みや英語名など、カード名の代わりに受け付ける名前
 pub(crate) fn roughly_card_name_equal(
     input_card_name: &str,
     card_name: &str,
-    card_name_ruby: &str,
+    alternate_name: Option<&str>,
 ) -> bool {
     let eq = |input: &str, target: &str| -> bool {
         info!(
@@ -102,5 +303,43 @@ pub(crate) fn roughly_card_name_equal(
         // 上と統合すべきかもしれないが、この書き方の方が「基本true、どこかでうまくいかなかったらfalse」という雰囲気になると思われる
         true
     };
-    eq(input_card_name, card_name) || eq(input_card_name, card_name_ruby)
+    eq(input_card_name, card_name)
+        || alternate_name.is_some_and(|alternate_name| eq(input_card_name, alternate_name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_into_sentences() {
+        assert_eq!(
+            split_sentences("このカードは「A。B」として扱う。相手は１枚ドローする。\n（この効果は無効化されない）"),
+            vec![
+                "このカードは「A。B」として扱う。",
+                "相手は１枚ドローする。",
+                "（この効果は無効化されない）"
+            ]
+        );
+    }
+
+    #[test]
+    fn split_single_segment_into_sentences() {
+        assert_eq!(
+            partial_segments(vec!["飛行。警戒。".to_owned()]),
+            vec!["飛行。", "警戒。"]
+        );
+        let segments = vec!["①：効果。その後、効果。".to_owned(), "②：効果。".to_owned()];
+        assert_eq!(partial_segments(segments.clone()), segments);
+    }
+
+    #[test]
+    fn show_next_segment_until_the_end() {
+        let segments = vec!["a".to_owned(), "b".to_owned()];
+        assert_eq!(
+            next_segment(&segments, 1),
+            Some(("テキストの続き（2 / 2）".to_owned(), "b"))
+        );
+        assert_eq!(next_segment(&segments, 2), None);
+    }
 }
@@ -0,0 +1,273 @@
+pub mod db;
+
+use std::future::Future;
+
+use chrono::NaiveDate;
+use serde::{de::DeserializeOwned, Serialize};
+use serenity::{
+    all::{CommandDataOptionValue, CommandInteraction, GuildId, Mentionable, UserId},
+    builder::CreateCommandOption,
+    client::Context,
+};
+use tracing::info;
+
+use crate::buttons::prompt_sent;
+use crate::channels::check_command_channel;
+use crate::common::today;
+use crate::db::{QuizMode, QuizParams, QuizType};
+use crate::preferences::db::get_preferences;
+use crate::reply::{Deferred, Reply};
+use crate::settings::db::get_settings;
+use crate::{mtg, ygo, Bot};
+
+pub(crate) const ALREADY_ENTERED: &str =
+    "今日の一枚には既に挑戦しています。結果は `/quiz daily results` で確認できます";
+
+pub(crate) fn create_subcommand(c: CreateCommandOption) -> CreateCommandOption {
+    c.description("Quiz of the day shared by the whole server")
+        .add_sub_option(CreateCommandOption::new(
+            serenity::all::CommandOptionType::SubCommand,
+            "ygo",
+            "Start today's Yu-gi-oh! quiz",
+        ))
+        .add_sub_option(CreateCommandOption::new(
+            serenity::all::CommandOptionType::SubCommand,
+            "mtg",
+            "Start today's Magic:the Gathering quiz",
+        ))
+        .add_sub_option(CreateCommandOption::new(
+            serenity::all::CommandOptionType::SubCommand,
+            "results",
+            "Show who solved today's quiz",
+        ))
+}
+
+pub(crate) async fn receive_command(bot: &Bot, ctx: &Context, command: CommandInteraction) {
+    // 答えた人以外に結果が見えないよう、今日の一枚のやりとりは全て本人にだけ表示する
+    let Some(deferred) = Deferred::start(&ctx.http, command.id, &command.token, true).await else {
+        return;
+    };
+    if let Err(refusal) = check_command_channel(&bot.database, &command).await {
+        deferred.respond(Err(refusal)).await;
+        return;
+    }
+
+    let CommandDataOptionValue::SubCommandGroup(after_daily) = &command.data.options[0].value
+    else {
+        //unreachable
+        panic!()
+    };
+    let subc = after_daily[0].name.as_str();
+
+    info!(subc);
+    let result = match (command.guild_id, subc) {
+        (None, _) => Err("今日の一枚はサーバー内でのみ遊べます".to_owned()),
+        (Some(guild_id), "ygo") => command_start(bot, ctx, &command, guild_id, QuizType::Ygo).await,
+        (Some(guild_id), "mtg") => command_start(bot, ctx, &command, guild_id, QuizType::Mtg).await,
+        (Some(guild_id), "results") => command_results(bot, &command, guild_id)
+            .await
+            .map(|content| (None, Reply::text(content))),
+        _ => Err(format!("Unknown Command: {}", subc)),
+    };
+
+    if let Some((Some(quiz), prompt)) = deferred.respond_with(result).await {
+        prompt_sent(bot, &ctx.http, &command.token, &quiz, &prompt, false).await;
+    }
+}
+
+/// その日のカードを取得する
+/// カードの API は無作為にしか引けないので、その日最初の挑戦で 1 枚引いて保存し、
+/// 以降はサーバー内の全員に同じカードを出す。同時に引いた場合も先に保存された方に揃える
+async fn todays_card<T, F, Fut>(
+    bot: &Bot,
+    guild_id: &i64,
+    quiz_type: &QuizType,
+    date: &NaiveDate,
+    fetch_card: F,
+) -> Result<T, String>
+where
+    T: Serialize + DeserializeOwned + Send + Sync + Unpin + 'static,
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<T, String>>,
+{
+    if let Some(card) = db::get_card(&bot.database, guild_id, quiz_type, date)
+        .await
+        .map_err(|e| e.to_string())?
+    {
+        return Ok(card);
+    }
+
+    let card = fetch_card().await?;
+    db::insert_card(&bot.database, guild_id, quiz_type, date, &card)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    // 同時に選ばれていた場合に備え、登録されたものを読み直す
+    db::get_card(&bot.database, guild_id, quiz_type, date)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or("データベースでエラーが発生しました".to_owned())
+}
+
+async fn command_start(
+    bot: &Bot,
+    ctx: &Context,
+    command: &CommandInteraction,
+    guild_id: GuildId,
+    quiz_type: QuizType,
+) -> Result<(Option<crate::db::Quiz>, Reply), String> {
+    let guild_id: i64 = guild_id.into();
+    let user_id: i64 = command.user.id.into();
+    let date = today();
+
+    let settings = get_settings(&bot.database, Some(guild_id))
+        .await
+        .map_err(|e| e.to_string())?;
+    if !settings.is_enabled(quiz_type) {
+        return Err("このサーバーではこのゲームのクイズは無効になっています".to_owned());
+    }
+    let preferences = get_preferences(&bot.database, &user_id)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    // カードを引く前に断っておく。同時に始めた場合は、クイズを登録するときに断る
+    if db::get_entry(&bot.database, &guild_id, &quiz_type, &date, &user_id)
+        .await
+        .map_err(|e| e.to_string())?
+        .is_some()
+    {
+        return Ok((None, Reply::text(ALREADY_ENTERED)));
+    }
+
+    let params = QuizParams {
+        user_id: Some(user_id),
+        channel_id: command.channel_id.into(),
+        guild_id: Some(guild_id),
+        quiz_mode: QuizMode::Text,
+        time_limit: None,
+        daily_date: Some(date),
+        mask_card_name: settings.mask_card_name,
+        private: true,
+        concurrency: preferences.concurrency,
+        text_as_image: false,
+    };
+    let prefix = format!("【{} の一枚】", date.format("%Y/%m/%d"));
+
+    let (quiz, reply) = match quiz_type {
+        QuizType::Ygo => {
+            let card = todays_card(bot, &guild_id, &quiz_type, &date, || {
+                ygo::fetch_card(&bot.database, &bot.http_client, None, QuizMode::Text)
+            })
+            .await?;
+            ygo::start_quiz(
+                &bot.database,
+                &bot.http_client,
+                ctx,
+                &params,
+                &card,
+                &prefix,
+                &[],
+            )
+            .await
+        }
+        QuizType::Mtg => {
+            let card = todays_card(bot, &guild_id, &quiz_type, &date, || {
+                mtg::fetch_card(
+                    &bot.database,
+                    &bot.http_client,
+                    &settings.mtg_format,
+                    false,
+                    QuizMode::Text,
+                )
+            })
+            .await?;
+            mtg::start_quiz(
+                &bot.database,
+                &bot.http_client,
+                ctx,
+                &params,
+                &card,
+                &prefix,
+                &[],
+            )
+            .await
+        }
+    }?;
+
+    Ok((Some(quiz), reply))
+}
+
+async fn command_results(
+    bot: &Bot,
+    command: &CommandInteraction,
+    guild_id: GuildId,
+) -> Result<String, String> {
+    let guild_id: i64 = guild_id.into();
+    let user_id: i64 = command.user.id.into();
+    let date = today();
+
+    let entries = db::get_entries(&bot.database, &guild_id, &date)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let mut content = format!("【{} の一枚】の結果\n", date.format("%Y/%m/%d"));
+    for quiz_type in [QuizType::Ygo, QuizType::Mtg] {
+        let type_entries = entries
+            .iter()
+            .filter(|entry| entry.quiz_type == quiz_type)
+            .collect::<Vec<_>>();
+        if type_entries.is_empty() {
+            continue;
+        }
+
+        // 答えは、自分が解き終わるまで見せない
+        let finished = type_entries
+            .iter()
+            .any(|entry| entry.user_id == user_id && entry.solved.is_some());
+        let answer = if finished {
+            match quiz_type {
+                QuizType::Ygo => {
+                    db::get_card::<ygo::Card>(&bot.database, &guild_id, &quiz_type, &date)
+                        .await
+                        .map_err(|e| e.to_string())?
+                        .map(|card| format!("（正解は「{}」）", card.card_name))
+                }
+                QuizType::Mtg => {
+                    db::get_card::<mtg::Card>(&bot.database, &guild_id, &quiz_type, &date)
+                        .await
+                        .map_err(|e| e.to_string())?
+                        .map(|card| format!("（正解は「{}」）", card.card_name))
+                }
+            }
+            .unwrap_or_default()
+        } else {
+            "".to_owned()
+        };
+
+        content += &format!(
+            "\n{}{}\n",
+            match quiz_type {
+                QuizType::Ygo => "遊戯王",
+                QuizType::Mtg => "Magic",
+            },
+            answer
+        );
+        for entry in type_entries {
+            let user = UserId::new(entry.user_id as u64).mention();
+            content += &match (entry.solved, entry.attempts, entry.hints) {
+                (Some(true), Some(attempts), Some(hints)) => format!(
+                    "- ✅ {} : {} 回目で正解（ヒント {} 回）\n",
+                    user, attempts, hints
+                ),
+                (Some(_), _, _) => format!("- ❌ {} : 不正解\n", user),
+                (None, _, _) => format!("- ⏳ {} : 挑戦中\n", user),
+            };
+        }
+    }
+
+    if entries.is_empty() {
+        content += "\nまだ誰も挑戦していません (`/quiz daily <タイプ>` で挑戦)\n";
+    }
+
+    Ok(content)
+}
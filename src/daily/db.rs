@@ -0,0 +1,159 @@
+use chrono::NaiveDate;
+use serde::{de::DeserializeOwned, Serialize};
+use sqlx::{types::Json, FromRow, PgConnection, PgPool};
+
+use crate::db::{Quiz, QuizOutcome, QuizType};
+
+#[allow(dead_code)]
+#[derive(FromRow)]
+pub struct Entry {
+    pub guild_id: i64,
+    pub quiz_type: QuizType,
+    pub quiz_date: NaiveDate,
+    pub user_id: i64,
+    /// 挑戦中は `None`
+    pub solved: Option<bool>,
+    pub attempts: Option<i32>,
+    pub hints: Option<i32>,
+}
+
+pub(crate) async fn get_card<T: DeserializeOwned + Send + Unpin + 'static>(
+    pool: &PgPool,
+    guild_id: &i64,
+    quiz_type: &QuizType,
+    quiz_date: &NaiveDate,
+) -> Result<Option<T>, sqlx::Error> {
+    let data: Option<Json<T>> = sqlx::query_scalar(
+        r#"SELECT card FROM daily_card WHERE guild_id = $1 AND quiz_type = $2 AND quiz_date = $3"#,
+    )
+    .bind(guild_id)
+    .bind(quiz_type)
+    .bind(quiz_date)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(data.map(|card| card.0))
+}
+
+/// その日のカードを登録する。既に他の人が登録していた場合はそちらを優先する
+pub(crate) async fn insert_card<T: Serialize + Sync>(
+    pool: &PgPool,
+    guild_id: &i64,
+    quiz_type: &QuizType,
+    quiz_date: &NaiveDate,
+    card: &T,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+      INSERT INTO daily_card (guild_id, quiz_type, quiz_date, card)
+      VALUES ($1, $2, $3, $4)
+      ON CONFLICT DO NOTHING
+    "#,
+    )
+    .bind(guild_id)
+    .bind(quiz_type)
+    .bind(quiz_date)
+    .bind(Json(card))
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub(crate) async fn get_entry(
+    pool: &PgPool,
+    guild_id: &i64,
+    quiz_type: &QuizType,
+    quiz_date: &NaiveDate,
+    user_id: &i64,
+) -> Result<Option<Entry>, sqlx::Error> {
+    let data: Option<Entry> = sqlx::query_as(
+        r#"
+      SELECT * FROM daily_entry
+      WHERE guild_id = $1 AND quiz_type = $2 AND quiz_date = $3 AND user_id = $4
+    "#,
+    )
+    .bind(guild_id)
+    .bind(quiz_type)
+    .bind(quiz_date)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(data)
+}
+
+pub(crate) async fn get_entries(
+    pool: &PgPool,
+    guild_id: &i64,
+    quiz_date: &NaiveDate,
+) -> Result<Vec<Entry>, sqlx::Error> {
+    let data: Vec<Entry> = sqlx::query_as(
+        r#"
+      SELECT * FROM daily_entry
+      WHERE guild_id = $1 AND quiz_date = $2
+      ORDER BY quiz_type, solved DESC NULLS LAST, attempts, hints
+    "#,
+    )
+    .bind(guild_id)
+    .bind(quiz_date)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(data)
+}
+
+/// その日の挑戦を始める。既に挑戦していれば `false` を返し、何もしない
+pub(crate) async fn insert_entry(
+    tx: &mut PgConnection,
+    guild_id: &i64,
+    quiz_type: &QuizType,
+    quiz_date: &NaiveDate,
+    user_id: &i64,
+) -> Result<bool, sqlx::Error> {
+    let inserted: Option<i64> = sqlx::query_scalar(
+        r#"
+      INSERT INTO daily_entry (guild_id, quiz_type, quiz_date, user_id)
+      VALUES ($1, $2, $3, $4)
+      ON CONFLICT DO NOTHING
+      RETURNING user_id
+    "#,
+    )
+    .bind(guild_id)
+    .bind(quiz_type)
+    .bind(quiz_date)
+    .bind(user_id)
+    .fetch_optional(tx)
+    .await?;
+
+    Ok(inserted.is_some())
+}
+
+pub(crate) async fn finish_entry(
+    tx: &mut PgConnection,
+    guild_id: &i64,
+    quiz_date: &NaiveDate,
+    quiz: &Quiz,
+    outcome: QuizOutcome,
+) -> Result<(), sqlx::Error> {
+    let solved = outcome == QuizOutcome::Correct;
+
+    sqlx::query(
+        r#"
+      UPDATE daily_entry SET solved = $5, attempts = $6, hints = $7
+      WHERE guild_id = $1 AND quiz_type = $2 AND quiz_date = $3 AND user_id = $4
+    "#,
+    )
+    .bind(guild_id)
+    .bind(quiz.quiz_type)
+    .bind(quiz_date)
+    .bind(quiz.user_id)
+    .bind(solved)
+    // 正解した回答も回数に含める
+    .bind(quiz.attempts + solved as i32)
+    .bind(quiz.hints)
+    .execute(tx)
+    .await?;
+
+    Ok(())
+}
@@ -1,8 +1,14 @@
+use std::time::Duration;
+
+use chrono::{DateTime, NaiveDate, Utc};
 use sqlx::{FromRow, PgConnection, PgPool};
 
 use crate::{mtg, ygo};
 
-#[derive(sqlx::Type)]
+/// 出題に使えなかったカードを、引き直しの対象にしておく期間
+const SKIP_PERIOD: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+#[derive(sqlx::Type, Clone, Copy, PartialEq)]
 #[sqlx(type_name = "quiz_type")]
 pub enum QuizType {
     #[sqlx(rename = "ygo")]
@@ -11,74 +17,625 @@ pub enum QuizType {
     Mtg,
 }
 
+impl QuizType {
+    pub fn from_option(value: &str) -> Option<QuizType> {
+        match value {
+            "ygo" => Some(QuizType::Ygo),
+            "mtg" => Some(QuizType::Mtg),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            QuizType::Ygo => "遊戯王",
+            QuizType::Mtg => "Magic",
+        }
+    }
+}
+
+#[derive(sqlx::Type, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[sqlx(type_name = "quiz_mode")]
+pub enum QuizMode {
+    /// カードテキストから当てる
+    #[sqlx(rename = "text")]
+    Text,
+    /// フレーバーテキストから当てる
+    #[sqlx(rename = "flavor")]
+    Flavor,
+    /// 効果（Magic では能力）を 1 つだけ見て当てる
+    #[sqlx(rename = "effect")]
+    Effect,
+    /// 効果（Magic では能力）や文を 1 つずつ見せ、誤答やヒントのたびに続きを見せる
+    #[sqlx(rename = "partial")]
+    Partial,
+    /// カード名とテキストを見せ、テキストの空欄に入る言葉を当てる
+    #[sqlx(rename = "blank")]
+    Blank,
+}
+
+impl QuizMode {
+    pub fn from_option(value: Option<&str>) -> QuizMode {
+        match value {
+            Some("flavor") => QuizMode::Flavor,
+            Some("effect") => QuizMode::Effect,
+            Some("partial") => QuizMode::Partial,
+            Some("blank") => QuizMode::Blank,
+            _ => QuizMode::Text,
+        }
+    }
+}
+
+/// 一人が同時に持てるクイズの範囲
+#[derive(sqlx::Type, Clone, Copy, PartialEq)]
+#[sqlx(type_name = "quiz_concurrency")]
+pub enum Concurrency {
+    /// サーバーごとに 1 問だけ。新しく始めると前のクイズは消える
+    #[sqlx(rename = "single")]
+    Single,
+    /// ゲームごとに 1 問ずつ
+    #[sqlx(rename = "per_game")]
+    PerGame,
+    /// チャンネルごとに 1 問ずつ
+    #[sqlx(rename = "per_channel")]
+    PerChannel,
+}
+
+impl Concurrency {
+    pub fn from_option(value: &str) -> Option<Concurrency> {
+        match value {
+            "single" => Some(Concurrency::Single),
+            "per_game" => Some(Concurrency::PerGame),
+            "per_channel" => Some(Concurrency::PerChannel),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Concurrency::Single => "1 問だけ",
+            Concurrency::PerGame => "ゲームごとに 1 問",
+            Concurrency::PerChannel => "チャンネルごとに 1 問",
+        }
+    }
+}
+
+#[derive(sqlx::Type, Clone, Copy, PartialEq)]
+#[sqlx(type_name = "quiz_outcome")]
+pub enum QuizOutcome {
+    #[sqlx(rename = "correct")]
+    Correct,
+    #[sqlx(rename = "giveup")]
+    Giveup,
+    #[sqlx(rename = "timeout")]
+    Timeout,
+    /// 長期間放置されたので片付けた
+    #[sqlx(rename = "expired")]
+    Expired,
+}
+
+#[allow(dead_code)]
 #[derive(FromRow)]
 pub struct Quiz {
-    pub user_id: i64,
+    pub id: i64,
+    /// 早押しクイズ（チャンネルの誰でも回答できる）の場合は `None`
+    pub user_id: Option<i64>,
     pub quiz_type: QuizType,
+    pub quiz_mode: QuizMode,
+    pub attempts: i32,
+    pub hints: i32,
+    pub channel_id: i64,
+    pub deadline: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub guild_id: Option<i64>,
+    /// 「今日の一枚」であれば、その日付
+    pub daily_date: Option<NaiveDate>,
+    /// 本人にだけ表示するクイズか
+    pub private: bool,
+    pub prompt_message_id: Option<i64>,
+    /// 出題がインタラクションへの応答だった場合、そのトークン
+    pub prompt_token: Option<String>,
+    pub thread_id: Option<i64>,
+    /// テキストを少しずつ見せる出題で、テキストを分けた数
+    pub segments: Option<i32>,
+    /// カードテキストを画像で送ったか。続きのテキストも画像で送る
+    pub text_as_image: bool,
+    /// 穴埋めの出題で、空欄に入る言葉
+    pub blank_answer: Option<String>,
+    /// 出題時にカード名を伏せたか。続きのテキストも同じように伏せる
+    pub mask_card_name: bool,
+}
+
+impl Quiz {
+    /// 誤答とヒントを合わせた、これまでに出したヒントの段階
+    pub fn reveal_level(&self) -> usize {
+        (self.attempts + self.hints) as usize
+    }
+
+    /// テキストを少しずつ見せる出題の得点（100 点満点）
+    /// 最初のテキストだけで正解すれば満点で、見せたテキストが増えるほど下がる
+    pub fn score(&self, outcome: QuizOutcome) -> Option<i32> {
+        let segments = self.segments?.max(1);
+        if outcome != QuizOutcome::Correct {
+            return Some(0);
+        }
+        let shown = (self.reveal_level() as i32 + 1).min(segments);
+
+        Some(100 * (segments - shown + 1) / segments)
+    }
+}
+
+/// 各ゲームに共通する、クイズ開始時の設定
+pub struct QuizParams {
+    /// 早押しクイズの場合は `None`
+    pub user_id: Option<i64>,
+    pub channel_id: i64,
+    pub guild_id: Option<i64>,
+    pub quiz_mode: QuizMode,
+    /// 制限時間（秒）
+    pub time_limit: Option<i64>,
+    pub daily_date: Option<NaiveDate>,
+    /// 問題文中のカード名を伏せるか
+    pub mask_card_name: bool,
+    pub private: bool,
+    /// 同時に持てるクイズの範囲。早押しクイズでは使わない
+    pub concurrency: Concurrency,
+    /// カードテキストを文字ではなく画像で送るか
+    pub text_as_image: bool,
+}
+
+/// コマンドの対象のクイズを探す
+/// 自分のクイズのスレッド、このチャンネルのクイズ、自分のクイズ、新しいクイズの順に優先する
+pub async fn find_quiz(
+    pool: &PgPool,
+    user_id: &i64,
+    channel_id: &i64,
+    guild_id: Option<i64>,
+    quiz_type: Option<QuizType>,
+) -> Result<Quiz, sqlx::Error> {
+    let data: Quiz = sqlx::query_as(
+        r#"
+      SELECT * FROM quiz
+      WHERE (user_id = $1 OR (user_id IS NULL AND channel_id = $2))
+        AND guild_id IS NOT DISTINCT FROM $3
+        AND ($4::quiz_type IS NULL OR quiz_type = $4)
+      ORDER BY thread_id IS NOT DISTINCT FROM $2 DESC, channel_id = $2 DESC,
+        user_id IS NOT NULL DESC, created_at DESC
+      LIMIT 1
+    "#,
+    )
+    .bind(user_id)
+    .bind(channel_id)
+    .bind(guild_id)
+    .bind(quiz_type)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(data)
+}
+
+/// ユーザーがこのサーバーで挑戦中のクイズと、このチャンネルの早押しクイズ
+pub async fn get_active_quizzes(
+    pool: &PgPool,
+    user_id: &i64,
+    channel_id: &i64,
+    guild_id: Option<i64>,
+) -> Result<Vec<Quiz>, sqlx::Error> {
+    let data: Vec<Quiz> = sqlx::query_as(
+        r#"
+      SELECT * FROM quiz
+      WHERE (user_id = $1 OR (user_id IS NULL AND channel_id = $2))
+        AND guild_id IS NOT DISTINCT FROM $3
+      ORDER BY created_at
+    "#,
+    )
+    .bind(user_id)
+    .bind(channel_id)
+    .bind(guild_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(data)
 }
 
-pub async fn get_quiz_type(pool: &PgPool, user_id: &i64) -> Result<QuizType, sqlx::Error> {
-    let data: Quiz = sqlx::query_as(r#"SELECT * FROM quiz WHERE user_id = $1"#)
-        .bind(user_id)
+pub async fn get_quiz_by_id(pool: &PgPool, quiz_id: &i64) -> Result<Quiz, sqlx::Error> {
+    let data: Quiz = sqlx::query_as(r#"SELECT * FROM quiz WHERE id = $1"#)
+        .bind(quiz_id)
         .fetch_one(pool)
         .await?;
 
-    Ok(data.quiz_type)
+    Ok(data)
 }
 
-pub async fn insert_quiz(
-    tx: &mut PgConnection,
-    user_id: &i64,
-    quiz_type: &QuizType,
+pub async fn get_timed_quizzes(pool: &PgPool) -> Result<Vec<Quiz>, sqlx::Error> {
+    let data: Vec<Quiz> = sqlx::query_as(r#"SELECT * FROM quiz WHERE deadline IS NOT NULL"#)
+        .fetch_all(pool)
+        .await?;
+
+    Ok(data)
+}
+
+/// `max_age` より前に作られ、放置されているクイズ
+/// 制限時間付きのものはタイマーが片付けるので含めない
+pub async fn get_abandoned_quizzes(
+    pool: &PgPool,
+    max_age: &Duration,
+) -> Result<Vec<Quiz>, sqlx::Error> {
+    let data: Vec<Quiz> = sqlx::query_as(
+        r#"
+      SELECT * FROM quiz
+      WHERE deadline IS NULL AND created_at < now() - make_interval(secs => $1)
+    "#,
+    )
+    .bind(max_age.as_secs_f64())
+    .fetch_all(pool)
+    .await?;
+
+    Ok(data)
+}
+
+pub async fn add_attempt(pool: &PgPool, quiz_id: &i64) -> Result<Quiz, sqlx::Error> {
+    let data: Quiz =
+        sqlx::query_as(r#"UPDATE quiz SET attempts = attempts + 1 WHERE id = $1 RETURNING *"#)
+            .bind(quiz_id)
+            .fetch_one(pool)
+            .await?;
+
+    Ok(data)
+}
+
+pub async fn add_hint(pool: &PgPool, quiz_id: &i64) -> Result<Quiz, sqlx::Error> {
+    let data: Quiz =
+        sqlx::query_as(r#"UPDATE quiz SET hints = hints + 1 WHERE id = $1 RETURNING *"#)
+            .bind(quiz_id)
+            .fetch_one(pool)
+            .await?;
+
+    Ok(data)
+}
+
+/// 出題メッセージの場所を記録する
+pub async fn set_prompt(
+    pool: &PgPool,
+    quiz_id: &i64,
+    message_id: &i64,
+    token: Option<&str>,
 ) -> Result<(), sqlx::Error> {
-    delete_tx_quiz(tx, user_id).await?;
+    sqlx::query(r#"UPDATE quiz SET prompt_message_id = $2, prompt_token = $3 WHERE id = $1"#)
+        .bind(quiz_id)
+        .bind(message_id)
+        .bind(token)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn set_thread(pool: &PgPool, quiz_id: &i64, thread_id: &i64) -> Result<(), sqlx::Error> {
+    sqlx::query(r#"UPDATE quiz SET thread_id = $2 WHERE id = $1"#)
+        .bind(quiz_id)
+        .bind(thread_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
 
+/// 書き込みを回答として受け付けるクイズを探す
+/// クイズ用のスレッドか、早押しクイズを出題中のチャンネルが対象
+pub async fn find_channel_quiz(
+    pool: &PgPool,
+    channel_id: &i64,
+) -> Result<Option<Quiz>, sqlx::Error> {
+    let data: Option<Quiz> = sqlx::query_as(
+        r#"
+      SELECT * FROM quiz
+      WHERE thread_id = $1 OR (user_id IS NULL AND channel_id = $1)
+      ORDER BY thread_id IS NOT DISTINCT FROM $1 DESC
+      LIMIT 1
+    "#,
+    )
+    .bind(channel_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(data)
+}
+
+/// 出題に使えなかったカードを記録する
+pub async fn record_skipped_card(
+    pool: &PgPool,
+    quiz_type: QuizType,
+    card_id: &str,
+    reason: &str,
+) -> Result<(), sqlx::Error> {
     sqlx::query(
         r#"
-      INSERT INTO quiz (user_id, quiz_type)
-      VALUES ($1, $2)
+      INSERT INTO skipped_card (quiz_type, card_id, reason)
+      VALUES ($1, $2, $3)
+      ON CONFLICT (quiz_type, card_id) DO UPDATE
+        SET reason = EXCLUDED.reason, skipped_at = now()
     "#,
     )
-    .bind(&user_id)
-    .bind(&quiz_type)
-    .execute(&mut *tx)
+    .bind(quiz_type)
+    .bind(card_id)
+    .bind(reason)
+    .execute(pool)
     .await?;
 
     Ok(())
 }
 
-pub async fn delete_quiz(pool: &PgPool, user_id: &i64) -> Result<(), sqlx::Error> {
+/// 最近、出題に使えなかったカードか
+/// サイトの変更などで一時的に使えなかっただけの場合に備え、古い記録は無視する
+pub async fn is_skipped_card(
+    pool: &PgPool,
+    quiz_type: QuizType,
+    card_id: &str,
+) -> Result<bool, sqlx::Error> {
+    let skipped: bool = sqlx::query_scalar(
+        r#"
+      SELECT EXISTS (
+        SELECT 1 FROM skipped_card
+        WHERE quiz_type = $1 AND card_id = $2 AND skipped_at > now() - make_interval(secs => $3)
+      )
+    "#,
+    )
+    .bind(quiz_type)
+    .bind(card_id)
+    .bind(SKIP_PERIOD.as_secs_f64())
+    .fetch_one(pool)
+    .await?;
+
+    Ok(skipped)
+}
+
+/// ユーザー（早押しクイズならチャンネル）のこのサーバーでのクイズを作り直す
+/// ユーザーのクイズは `concurrency` の範囲で同時に 1 問だけになるよう、古いものを消す
+/// 今日の一枚に既に挑戦していれば `None` を返す。呼び出し側はトランザクションを確定しないこと
+/// ゲームごとのテーブルへの挿入は呼び出し側で行うこと
+pub async fn insert_quiz(
+    tx: &mut PgConnection,
+    params: &QuizParams,
+    quiz_type: &QuizType,
+    segments: Option<i32>,
+    blank_answer: Option<&str>,
+) -> Result<Option<(Quiz, Vec<Quiz>)>, sqlx::Error> {
+    // 同時に始めても 1 日 1 回になるよう、クイズを作る前に挑戦の記録を取る
+    if let (Some(guild_id), Some(daily_date), Some(user_id)) =
+        (params.guild_id, params.daily_date, params.user_id)
+    {
+        if !crate::daily::db::insert_entry(tx, &guild_id, quiz_type, &daily_date, &user_id).await? {
+            return Ok(None);
+        }
+    }
+
+    let old_quizzes: Vec<Quiz> = sqlx::query_as(
+        r#"
+      SELECT * FROM quiz
+      WHERE user_id IS NOT DISTINCT FROM $1 AND ($1 IS NOT NULL OR channel_id = $2)
+        AND guild_id IS NOT DISTINCT FROM $3
+    "#,
+    )
+    .bind(params.user_id)
+    .bind(params.channel_id)
+    .bind(params.guild_id)
+    .fetch_all(&mut *tx)
+    .await?;
+    let replaced = old_quizzes
+        .into_iter()
+        .filter(|old_quiz| is_replaced_by(old_quiz, params, *quiz_type))
+        .collect::<Vec<_>>();
+    for old_quiz in &replaced {
+        delete_tx_quiz(tx, &old_quiz.id).await?;
+    }
+
+    let data: Quiz = sqlx::query_as(
+        r#"
+      INSERT INTO quiz
+        (user_id, quiz_type, quiz_mode, channel_id, deadline, guild_id, daily_date, private,
+         segments, text_as_image, blank_answer, mask_card_name)
+      VALUES ($1, $2, $3, $4, now() + make_interval(secs => $5), $6, $7, $8, $9, $10, $11, $12)
+      RETURNING *
+    "#,
+    )
+    .bind(params.user_id)
+    .bind(quiz_type)
+    .bind(params.quiz_mode)
+    .bind(params.channel_id)
+    .bind(params.time_limit.map(|secs| secs as f64))
+    .bind(params.guild_id)
+    .bind(params.daily_date)
+    .bind(params.private)
+    .bind(segments)
+    .bind(params.text_as_image)
+    .bind(blank_answer)
+    .bind(params.mask_card_name)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    Ok(Some((data, replaced)))
+}
+
+/// 新しく始めるクイズによって、前のクイズが消えるか
+/// 「今日の一枚」は 1 日 1 回しか挑戦できないので、同時に持てる範囲によらず残す
+fn is_replaced_by(old_quiz: &Quiz, params: &QuizParams, quiz_type: QuizType) -> bool {
+    old_quiz.daily_date.is_none()
+        && match params.concurrency {
+            Concurrency::Single => true,
+            Concurrency::PerGame => old_quiz.quiz_type == quiz_type,
+            Concurrency::PerChannel => old_quiz.channel_id == params.channel_id,
+        }
+}
+
+/// クイズの結果を記録してから削除する
+/// `answered_by` は早押しクイズで正解したユーザー
+/// 既に他の経路で終了していた場合は `None` を返す
+pub async fn finish_quiz(
+    pool: &PgPool,
+    quiz_id: &i64,
+    outcome: QuizOutcome,
+    answered_by: Option<i64>,
+) -> Result<Option<Quiz>, sqlx::Error> {
     let mut tx = pool.begin().await?;
 
-    delete_tx_quiz(&mut tx, user_id).await?;
+    let finished: Option<Quiz> = sqlx::query_as(r#"SELECT * FROM quiz WHERE id = $1 FOR UPDATE"#)
+        .bind(quiz_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+    if let Some(quiz) = &finished {
+        sqlx::query(
+            r#"
+          INSERT INTO quiz_result (user_id, quiz_type, outcome, attempts, hints, guild_id, score)
+          VALUES ($1, $2, $3, $4, $5, $6, $7)
+        "#,
+        )
+        .bind(answered_by.or(quiz.user_id))
+        .bind(quiz.quiz_type)
+        .bind(outcome)
+        .bind(quiz.attempts)
+        .bind(quiz.hints)
+        .bind(quiz.guild_id)
+        .bind(quiz.score(outcome))
+        .execute(&mut *tx)
+        .await?;
+
+        if let (Some(guild_id), Some(daily_date)) = (quiz.guild_id, quiz.daily_date) {
+            crate::daily::db::finish_entry(&mut tx, &guild_id, &daily_date, quiz, outcome).await?;
+        }
+
+        delete_tx_quiz(&mut tx, quiz_id).await?;
+    }
 
     tx.commit().await?;
 
-    Ok(())
+    Ok(finished)
 }
 
 pub(crate) async fn delete_tx_quiz(
     tx: &mut PgConnection,
-    user_id: &i64,
+    quiz_id: &i64,
 ) -> Result<(), sqlx::Error> {
-    let willbe_deleted: Option<Quiz> = sqlx::query_as(r#"SELECT * FROM quiz WHERE user_id = $1"#)
-        .bind(user_id)
+    let willbe_deleted: Option<Quiz> = sqlx::query_as(r#"SELECT * FROM quiz WHERE id = $1"#)
+        .bind(quiz_id)
         .fetch_optional(&mut *tx)
         .await?;
 
-    match willbe_deleted {
-        Some(quiz) => match quiz.quiz_type {
-            QuizType::Ygo => ygo::db::delete_quiz(tx, user_id).await,
-            QuizType::Mtg => mtg::db::delete_quiz(tx, user_id).await,
+    if let Some(quiz) = willbe_deleted {
+        match quiz.quiz_type {
+            QuizType::Ygo => ygo::db::delete_quiz(tx, quiz_id).await,
+            QuizType::Mtg => mtg::db::delete_quiz(tx, quiz_id).await,
         }
-        .unwrap(),
-        None => {}
+        .unwrap()
     }
 
-    sqlx::query(r#"DELETE FROM quiz WHERE user_id = $1"#)
-        .bind(user_id)
+    sqlx::query(r#"DELETE FROM quiz WHERE id = $1"#)
+        .bind(quiz_id)
         .execute(&mut *tx)
         .await?;
 
     Ok(())
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub(crate) fn quiz(
+        quiz_type: QuizType,
+        channel_id: i64,
+        daily_date: Option<NaiveDate>,
+    ) -> Quiz {
+        Quiz {
+            id: 1,
+            user_id: Some(1),
+            quiz_type,
+            quiz_mode: QuizMode::Text,
+            attempts: 0,
+            hints: 0,
+            channel_id,
+            deadline: None,
+            created_at: Utc::now(),
+            guild_id: Some(1),
+            daily_date,
+            private: false,
+            prompt_message_id: None,
+            prompt_token: None,
+            thread_id: None,
+            segments: None,
+            text_as_image: false,
+            blank_answer: None,
+            mask_card_name: true,
+        }
+    }
+
+    fn params(concurrency: Concurrency) -> QuizParams {
+        QuizParams {
+            user_id: Some(1),
+            channel_id: 10,
+            guild_id: Some(1),
+            quiz_mode: QuizMode::Text,
+            time_limit: None,
+            daily_date: None,
+            mask_card_name: true,
+            private: false,
+            concurrency,
+            text_as_image: false,
+        }
+    }
+
+    #[test]
+    fn replace_quizzes_by_concurrency() {
+        let ygo_here = quiz(QuizType::Ygo, 10, None);
+        let mtg_elsewhere = quiz(QuizType::Mtg, 20, None);
+
+        assert!(is_replaced_by(
+            &ygo_here,
+            &params(Concurrency::Single),
+            QuizType::Mtg
+        ));
+        assert!(is_replaced_by(
+            &mtg_elsewhere,
+            &params(Concurrency::Single),
+            QuizType::Ygo
+        ));
+        assert!(is_replaced_by(
+            &ygo_here,
+            &params(Concurrency::PerGame),
+            QuizType::Ygo
+        ));
+        assert!(!is_replaced_by(
+            &mtg_elsewhere,
+            &params(Concurrency::PerGame),
+            QuizType::Ygo
+        ));
+        assert!(is_replaced_by(
+            &ygo_here,
+            &params(Concurrency::PerChannel),
+            QuizType::Mtg
+        ));
+        assert!(!is_replaced_by(
+            &mtg_elsewhere,
+            &params(Concurrency::PerChannel),
+            QuizType::Mtg
+        ));
+    }
+
+    #[test]
+    fn daily_quiz_survives_new() {
+        let daily = quiz(
+            QuizType::Ygo,
+            10,
+            Some(NaiveDate::from_ymd_opt(2024, 1, 1).unwrap()),
+        );
+
+        for concurrency in [
+            Concurrency::Single,
+            Concurrency::PerGame,
+            Concurrency::PerChannel,
+        ] {
+            assert!(!is_replaced_by(&daily, &params(concurrency), QuizType::Ygo));
+        }
+    }
+}
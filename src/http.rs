@@ -0,0 +1,348 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use reqwest::{RequestBuilder, Response, StatusCode};
+use tokio::time::Instant;
+use tracing::info;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// 応答を読み終えるまでを含めた 1 回のリクエストの制限時間
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// 5xx や 429 が返った場合に再試行する回数
+const MAX_RETRIES: u32 = 3;
+/// 再試行までの待ち時間。再試行のたびに倍にする
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+/// `Retry-After` で指定されても、これより長くは待たない
+const MAX_BACKOFF: Duration = Duration::from_secs(10);
+
+/// 外部のサイトへのリクエストの間隔
+/// Scryfall は 50〜100ms 空けるよう求めている。遊戯王カードデータベースは控えめに取得する
+const HOST_INTERVALS: [(&str, Duration); 3] = [
+    ("api.scryfall.com", Duration::from_millis(100)),
+    ("db.ygoprodeck.com", Duration::from_millis(100)),
+    ("www.db.yugioh-card.com", Duration::from_secs(1)),
+];
+
+/// Bot 全体で共有する HTTP クライアント
+/// タイムアウト、失敗時の再試行、ホストごとのリクエスト間隔の調整を行う
+#[derive(Clone)]
+pub(crate) struct HttpClient {
+    client: reqwest::Client,
+    buckets: Arc<HashMap<String, Mutex<TokenBucket>>>,
+    initial_backoff: Duration,
+}
+
+impl HttpClient {
+    pub fn new() -> Result<HttpClient, String> {
+        HttpClient::with_intervals(
+            HOST_INTERVALS
+                .iter()
+                .map(|(host, interval)| (host.to_string(), *interval)),
+            INITIAL_BACKOFF,
+        )
+    }
+
+    fn with_intervals(
+        intervals: impl IntoIterator<Item = (String, Duration)>,
+        initial_backoff: Duration,
+    ) -> Result<HttpClient, String> {
+        let client = reqwest::Client::builder()
+            .connect_timeout(CONNECT_TIMEOUT)
+            .timeout(REQUEST_TIMEOUT)
+            .user_agent("ygo-quiz-bot/1.0")
+            .build()
+            .map_err(|e| e.to_string())?;
+
+        Ok(HttpClient {
+            client,
+            buckets: Arc::new(
+                intervals
+                    .into_iter()
+                    .map(|(host, interval)| (host, Mutex::new(TokenBucket::new(interval))))
+                    .collect(),
+            ),
+            initial_backoff,
+        })
+    }
+
+    pub fn get(&self, url: &str) -> RequestBuilder {
+        self.client.get(url)
+    }
+
+    /// リクエストを送る。5xx や 429、通信の失敗は間隔を空けて再試行する
+    /// それ以外のエラーの応答は、API のエラーの内容を読めるようそのまま返す
+    pub async fn send(&self, request: RequestBuilder) -> Result<Response, String> {
+        let request = request.build().map_err(|e| e.to_string())?;
+        let host = request.url().host_str().unwrap_or_default().to_owned();
+
+        let mut backoff = self.initial_backoff;
+        for retry in 0..=MAX_RETRIES {
+            let attempt = request.try_clone().ok_or("再試行できないリクエストです")?;
+            self.wait_turn(&host).await;
+
+            let wait = match self.client.execute(attempt).await {
+                Ok(response) if is_retryable(response.status()) && retry < MAX_RETRIES => {
+                    info!("{} returned {}, retrying", request.url(), response.status());
+                    retry_after(&response).unwrap_or(backoff)
+                }
+                Err(err) if (err.is_connect() || err.is_timeout()) && retry < MAX_RETRIES => {
+                    info!("{} failed: {}, retrying", request.url(), err);
+                    backoff
+                }
+                result => return result.map_err(|e| e.to_string()),
+            };
+            tokio::time::sleep(wait.min(MAX_BACKOFF)).await;
+            backoff *= 2;
+        }
+
+        unreachable!()
+    }
+
+    /// エラーの応答でも本文を返す。エラーの内容を本文で返す JSON の API に使う
+    pub async fn get_text(&self, request: RequestBuilder) -> Result<String, String> {
+        self.send(request)
+            .await?
+            .text()
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    /// 成功した場合だけ本文を返す。エラーのページを本来のページとして解析しないよう、HTML はこちらで取得する
+    pub async fn get_page(&self, request: RequestBuilder) -> Result<String, String> {
+        self.send_successfully(request)
+            .await?
+            .text()
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    /// 成功した場合だけ本文を返す
+    pub async fn get_bytes(&self, request: RequestBuilder) -> Result<Vec<u8>, String> {
+        Ok(self
+            .send_successfully(request)
+            .await?
+            .bytes()
+            .await
+            .map_err(|e| e.to_string())?
+            .to_vec())
+    }
+
+    /// エラーの応答は `Err` にする
+    async fn send_successfully(&self, request: RequestBuilder) -> Result<Response, String> {
+        let response = self.send(request).await?;
+        if !response.status().is_success() {
+            return Err(format!("{} ({})", response.status(), response.url()));
+        }
+
+        Ok(response)
+    }
+
+    /// ホストごとの間隔を守れるまで待つ
+    async fn wait_turn(&self, host: &str) {
+        let Some(bucket) = self.buckets.get(host) else {
+            return;
+        };
+        let wait = bucket.lock().unwrap().take(Instant::now());
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+fn is_retryable(status: StatusCode) -> bool {
+    status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+}
+
+fn retry_after(response: &Response) -> Option<Duration> {
+    response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .parse()
+        .ok()
+        .map(Duration::from_secs)
+}
+
+/// `interval` ごとに 1 つ貯まるトークンを、リクエストのたびに 1 つ使う
+/// 貯められるのは 1 つだけなので、連続したリクエストは必ず `interval` 以上空く
+struct TokenBucket {
+    interval: Duration,
+    /// 次のトークンが使えるようになる時刻
+    next: Instant,
+}
+
+impl TokenBucket {
+    fn new(interval: Duration) -> TokenBucket {
+        TokenBucket {
+            interval,
+            next: Instant::now(),
+        }
+    }
+
+    /// トークンを予約し、使えるようになるまでの待ち時間を返す
+    fn take(&mut self, now: Instant) -> Duration {
+        let at = self.next.max(now);
+        self.next = at + self.interval;
+        at - now
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn client(interval: Duration) -> HttpClient {
+        HttpClient::with_intervals(
+            [("127.0.0.1".to_owned(), interval)],
+            Duration::from_millis(10),
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn retry_server_errors() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/card"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(2)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/card"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("ok"))
+            .mount(&server)
+            .await;
+
+        let client = client(Duration::ZERO);
+        let body = client
+            .get_text(client.get(&format!("{}/card", server.uri())))
+            .await
+            .unwrap();
+
+        assert_eq!(body, "ok");
+        assert_eq!(server.received_requests().await.unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn retry_too_many_requests() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "0"))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&server)
+            .await;
+
+        let client = client(Duration::ZERO);
+        let response = client.send(client.get(&server.uri())).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(server.received_requests().await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn give_up_after_max_retries() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(500))
+            .mount(&server)
+            .await;
+
+        let client = client(Duration::ZERO);
+        let response = client.send(client.get(&server.uri())).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(
+            server.received_requests().await.unwrap().len(),
+            MAX_RETRIES as usize + 1
+        );
+        assert!(client.get_bytes(client.get(&server.uri())).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn return_client_errors_as_is() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(404).set_body_string(r#"{"object":"error"}"#))
+            .mount(&server)
+            .await;
+
+        let client = client(Duration::ZERO);
+        let body = client.get_text(client.get(&server.uri())).await.unwrap();
+
+        assert_eq!(body, r#"{"object":"error"}"#);
+        assert_eq!(server.received_requests().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn reject_error_pages() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/missing"))
+            .respond_with(ResponseTemplate::new(404).set_body_string("<html>Not Found</html>"))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/card"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("<html>card</html>"))
+            .mount(&server)
+            .await;
+
+        let client = client(Duration::ZERO);
+        assert!(client
+            .get_page(client.get(&format!("{}/missing", server.uri())))
+            .await
+            .is_err());
+        assert_eq!(
+            client
+                .get_page(client.get(&format!("{}/card", server.uri())))
+                .await
+                .unwrap(),
+            "<html>card</html>"
+        );
+    }
+
+    #[tokio::test]
+    async fn space_requests_to_the_same_host() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&server)
+            .await;
+
+        let interval = Duration::from_millis(100);
+        let client = client(interval);
+        let start = Instant::now();
+        for _ in 0..4 {
+            client.send(client.get(&server.uri())).await.unwrap();
+        }
+
+        assert!(start.elapsed() >= interval * 3);
+    }
+
+    #[test]
+    fn token_bucket_reserves_turns() {
+        let interval = Duration::from_millis(100);
+        let now = Instant::now();
+        let mut bucket = TokenBucket {
+            interval,
+            next: now,
+        };
+
+        assert_eq!(bucket.take(now), Duration::ZERO);
+        assert_eq!(bucket.take(now), interval);
+        assert_eq!(bucket.take(now), interval * 2);
+        // 間が空けば待たずに使える
+        assert_eq!(bucket.take(now + interval * 5), Duration::ZERO);
+    }
+}
@@ -1,26 +1,71 @@
+mod action;
+mod admin;
+mod art;
+mod blank;
+mod buttons;
+mod channels;
 mod common;
+mod daily;
 mod db;
+mod http;
+mod message;
 mod mtg;
+mod preferences;
+mod prefetch;
+mod reply;
+mod schedule;
+mod settings;
+mod sweeper;
+mod text_image;
+mod thread;
+mod timer;
 mod ygo;
 
+use std::num::NonZeroU64;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use action::Action;
 use anyhow::Context as _;
-use db::get_quiz_type;
+use db::{find_quiz, get_active_quizzes, QuizMode, QuizType};
+use http::HttpClient;
+use preferences::PrivateUsers;
+use prefetch::Prefetcher;
+use reply::{Deferred, Reply};
 use serenity::all::{
-    CommandInteraction, CreateCommandOption, CreateInteractionResponse,
-    CreateInteractionResponseMessage, Interaction,
+    Command, CommandDataOptionValue, CommandInteraction, CreateCommandOption,
+    CreateInteractionResponse, CreateInteractionResponseMessage, Interaction, Message,
 };
 use serenity::builder::CreateCommand;
 use serenity::model::gateway::Ready;
 use serenity::prelude::*;
-use serenity::{all::GuildId, async_trait};
+use serenity::{
+    all::{Guild, GuildId},
+    async_trait,
+};
 use shuttle_runtime::SecretStore;
 use shuttle_serenity::SerenityService;
 use sqlx::{Executor, PgPool};
 use tracing::{error, info};
 
+/// コマンドを登録する範囲
+enum CommandScope {
+    /// 全てのサーバーで使えるグローバルコマンドとして登録する
+    Global,
+    /// 指定したサーバーにだけ登録する
+    Guilds(Vec<GuildId>),
+}
+
 struct Bot {
     database: PgPool,
-    guild_id: String,
+    /// 外部の API やサイトへのリクエストは全てこれを通す
+    http_client: HttpClient,
+    /// 条件を指定しない `new` にすぐ応じられるよう、先読みしておくカード
+    ygo_cards: Prefetcher<QuizMode, ygo::Card>,
+    mtg_cards: Prefetcher<(String, QuizMode), mtg::Card>,
+    command_scope: CommandScope,
+    private_users: PrivateUsers,
+    /// 再接続のたびに `ready` が呼ばれるので、バックグラウンドの処理は一度だけ始める
+    tasks_started: AtomicBool,
 }
 
 impl Bot {
@@ -32,12 +77,22 @@ impl Bot {
                     CreateInteractionResponseMessage::new().content(
                         "Help:\n".to_owned()
                             + "クイズを出すので回答してください\n"
-                            + "ユーザーごとに別の問題に取り組むことができます。\n\n"
+                            + "ユーザーごとに別の問題に取り組むことができます。\n"
+                            + "`/quiz preferences concurrency` でゲームやチャンネルごとに同時に挑戦できます。\n"
+                            + "「早押し」のクイズはチャンネルの誰でも回答できます。\n\n"
                             + "Commands:\n"
-                            + "- `/quiz <type> new` - 開始\n"
-                            + "- `/quiz ans <answer>` - 回答\n"
+                            + "- `/quiz <type> new` - 開始（`private:true` で本人にだけ表示、`thread:true` でスレッドで出題、`image:true` でカードテキストを画像で表示、`mode:効果を 1 つずつ` で誤答やヒントのたびにテキストの続きを表示、`mode:穴埋め` でテキストの空欄に入る言葉を回答）\n"
+                            + "- `/quiz ans <answer>` - 回答（`game` で対象のクイズを指定）\n"
+                            + "- `/quiz hint` - ヒントをもらう\n"
                             + "- `/quiz giveup` - 問題を諦める\n"
-                            + "- `/quiz help` - このヘルプを表示\n",
+                            + "- `/quiz list` - 挑戦中のクイズを表示\n"
+                            + "- `/quiz daily <type>` - サーバー共通の「今日の一枚」に挑戦\n"
+                            + "- `/quiz daily results` - 「今日の一枚」の結果を表示\n"
+                            + "- `/quiz preferences` - 自分の設定（本人にだけ表示するかなど）\n"
+                            + "- `/quiz help` - このヘルプを表示\n"
+                            + "- `/quizadmin schedule` - 早押しクイズの定期投稿を管理（管理者向け）\n"
+                            + "- `/quizadmin settings` - サーバーの設定を確認・変更（管理者向け）\n"
+                            + "- `/quizadmin channels` - クイズを使えるチャンネルを管理（管理者向け）\n",
                     ),
                 ),
             )
@@ -45,123 +100,273 @@ impl Bot {
             .unwrap();
     }
 
-    async fn command_general(&self, ctx: &Context, command: &CommandInteraction) {
-        let msg = match get_quiz_type(&self.database, &command.user.id.into()).await {
-            Ok(quiz_type) => match command.data.options[0].name.as_str() {
-                "ans" => {
-                    let result = match quiz_type {
-                        db::QuizType::Ygo => ygo::command_ans(self, ctx, command).await,
-                        db::QuizType::Mtg => mtg::command_ans(self, ctx, command).await,
-                    };
-                    match result {
-                        Ok(msg) => msg,
-                        Err(msg) => {
-                            error!(msg);
-                            msg
-                        }
-                    }
-                }
-                "giveup" => {
-                    let result = match quiz_type {
-                        db::QuizType::Ygo => ygo::command_giveup(self, ctx, command).await,
-                        db::QuizType::Mtg => mtg::command_giveup(self, ctx, command).await,
-                    };
-                    match result {
-                        Ok(msg) => msg,
-                        Err(msg) => {
-                            error!(msg);
-                            msg
-                        }
-                    }
-                }
-                _ => "謎のコマンド".into(),
-            },
-            Err(err) => format!(
-                "データベースでエラーが発生しました (`/quiz <タイプ> new` は実行しましたか？) : {}",
-                err.to_string()
-            ),
+    async fn command_list(&self, ctx: &Context, command: &CommandInteraction) {
+        let content = match get_active_quizzes(
+            &self.database,
+            &command.user.id.into(),
+            &command.channel_id.into(),
+            command.guild_id.map(Into::into),
+        )
+        .await
+        {
+            Ok(quizzes) if quizzes.is_empty() => {
+                "挑戦中のクイズはありません (`/quiz <タイプ> new` で開始)".to_owned()
+            }
+            Ok(quizzes) => {
+                let now = chrono::Utc::now();
+                quizzes
+                    .iter()
+                    .fold("挑戦中のクイズ\n".to_owned(), |content, quiz| {
+                        content
+                            + &format!(
+                                "- {}{} : <#{}>（回答 {} 回、ヒント {} 回{}）\n",
+                                quiz.quiz_type.name(),
+                                match (quiz.user_id, quiz.daily_date) {
+                                    (None, _) => "【早押し】",
+                                    (_, Some(_)) => "【今日の一枚】",
+                                    _ => "",
+                                },
+                                quiz.thread_id.unwrap_or(quiz.channel_id),
+                                quiz.attempts,
+                                quiz.hints,
+                                quiz.deadline
+                                    .map(|deadline| format!(
+                                        "、残り {} 秒",
+                                        (deadline - now).num_seconds().max(0)
+                                    ))
+                                    .unwrap_or_default()
+                            )
+                    })
+            }
+            Err(err) => format!("データベースでエラーが発生しました：{}", err),
         };
-        command
+
+        if let Err(err) = command
             .create_response(
                 &ctx.http,
                 CreateInteractionResponse::Message(
-                    CreateInteractionResponseMessage::new().content(msg),
+                    CreateInteractionResponseMessage::new()
+                        .content(content)
+                        .ephemeral(true),
                 ),
             )
             .await
-            .unwrap();
+        {
+            error!("{}", err)
+        }
+    }
+
+    async fn command_general(&self, ctx: &Context, command: &CommandInteraction) {
+        let CommandDataOptionValue::SubCommand(options) = &command.data.options[0].value else {
+            //unreachable
+            panic!()
+        };
+        // 複数のクイズに挑戦中の場合は、ゲームを指定して選べる
+        let quiz_type = options.iter().find_map(|option| match &option.value {
+            CommandDataOptionValue::String(s) if option.name == "game" => QuizType::from_option(s),
+            _ => None,
+        });
+        let quiz = find_quiz(
+            &self.database,
+            &command.user.id.into(),
+            &command.channel_id.into(),
+            command.guild_id.map(Into::into),
+            quiz_type,
+        )
+        .await;
+
+        // 本人にだけ表示するクイズ（「今日の一枚」を含む）は、回答やヒントも本人にだけ表示する
+        let ephemeral = matches!(&quiz, Ok(quiz) if quiz.private || quiz.daily_date.is_some());
+        let Some(deferred) =
+            Deferred::start(&ctx.http, command.id, &command.token, ephemeral).await
+        else {
+            return;
+        };
+        if let Err(refusal) = channels::check_command_channel(&self.database, command).await {
+            deferred.respond(Err(refusal)).await;
+            return;
+        }
+
+        let result = match &quiz {
+            Ok(quiz) => match command.data.options[0].name.as_str() {
+                "ans" => {
+                    let answer = options
+                        .iter()
+                        .find(|option| option.name == "answer")
+                        .and_then(|option| option.value.as_str())
+                        .unwrap_or_default()
+                        .to_owned();
+                    action::run(self, ctx, &command.user, quiz, Action::Answer(answer)).await
+                }
+                "hint" => action::run(self, ctx, &command.user, quiz, Action::Hint).await,
+                "giveup" => action::run(self, ctx, &command.user, quiz, Action::Giveup).await,
+                _ => Ok(Reply::text("謎のコマンド")),
+            },
+            Err(err) => Err(format!(
+                "データベースでエラーが発生しました (`/quiz <タイプ> new` は実行しましたか？) : {}",
+                err
+            )),
+        };
+
+        deferred.respond(result).await;
+        if let Ok(quiz) = &quiz {
+            thread::archive_if_finished(self, &ctx.http, quiz).await;
+        }
+    }
+}
+
+const GAME_OPTION_DESCRIPTION: &str =
+    "The game of the quiz, if you have several. Defaults to the one in this channel";
+
+/// Bot が登録するコマンド一覧
+fn create_commands() -> Vec<CreateCommand> {
+    vec![
+        CreateCommand::new("quiz")
+            .description("Communicate with quiz bot")
+            .add_option(
+                CreateCommandOption::new(
+                    serenity::all::CommandOptionType::SubCommand,
+                    "ans",
+                    "Answer to quiz",
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(
+                        serenity::all::CommandOptionType::String,
+                        "answer",
+                        "The answer",
+                    )
+                    .required(true),
+                )
+                .add_sub_option(common::create_game_option(GAME_OPTION_DESCRIPTION)),
+            )
+            .add_option(
+                CreateCommandOption::new(
+                    serenity::all::CommandOptionType::SubCommand,
+                    "hint",
+                    "Get a hint for quiz",
+                )
+                .add_sub_option(common::create_game_option(GAME_OPTION_DESCRIPTION)),
+            )
+            .add_option(
+                CreateCommandOption::new(
+                    serenity::all::CommandOptionType::SubCommand,
+                    "giveup",
+                    "Giveup quiz",
+                )
+                .add_sub_option(common::create_game_option(GAME_OPTION_DESCRIPTION)),
+            )
+            .add_option(CreateCommandOption::new(
+                serenity::all::CommandOptionType::SubCommand,
+                "list",
+                "List your active quizzes",
+            ))
+            .add_option(CreateCommandOption::new(
+                serenity::all::CommandOptionType::SubCommand,
+                "help",
+                "Help of quiz bot",
+            ))
+            .add_option(preferences::create_subcommand(CreateCommandOption::new(
+                serenity::all::CommandOptionType::SubCommand,
+                "preferences",
+                "",
+            )))
+            .add_option(ygo::create_subcommand(CreateCommandOption::new(
+                serenity::all::CommandOptionType::SubCommandGroup,
+                "ygo",
+                "",
+            )))
+            .add_option(mtg::create_subcommand(CreateCommandOption::new(
+                serenity::all::CommandOptionType::SubCommandGroup,
+                "mtg",
+                "",
+            )))
+            .add_option(daily::create_subcommand(CreateCommandOption::new(
+                serenity::all::CommandOptionType::SubCommandGroup,
+                "daily",
+                "",
+            ))),
+        admin::create_command(),
+    ]
+}
+
+/// 指定したサーバーにコマンドを登録する
+async fn register_guild_commands(ctx: &Context, guild_id: &GuildId) {
+    match guild_id.set_commands(&ctx.http, create_commands()).await {
+        Ok(commands) => info!("Registered {} commands on {}", commands.len(), guild_id),
+        Err(err) => error!("Failed to register commands on {}: {}", guild_id, err),
     }
 }
 
 #[async_trait]
 impl EventHandler for Bot {
+    async fn message(&self, ctx: Context, msg: Message) {
+        message::receive_message(self, &ctx, &msg).await;
+    }
+
     async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
-        if let Interaction::Command(command) = interaction {
-            if command.user.bot || command.data.name.as_str() != "quiz" {
+        if let Interaction::Component(component) = interaction {
+            if !component.user.bot {
+                buttons::receive_component(self, &ctx, component).await;
+            }
+        } else if let Interaction::Modal(modal) = interaction {
+            if !modal.user.bot {
+                buttons::receive_modal(self, &ctx, modal).await;
+            }
+        } else if let Interaction::Command(command) = interaction {
+            if command.user.bot {
+                return;
+            }
+            if command.data.name.as_str() == "quizadmin" {
+                admin::receive_command(self, &ctx, command).await;
+                return;
+            }
+            if command.data.name.as_str() != "quiz" {
                 return;
             }
 
             info!("Received command interaction: {:#?}", command.data.options);
 
+            // ヘルプや個人の設定・一覧以外は、管理者が許可したチャンネルでだけ使える
+            // 確認にはデータベースを引くので、それぞれ応答を遅延させてから確認する
             match command.data.options[0].name.as_str() {
-                "ygo" => ygo::receive_command(&self, &ctx, command).await,
-                "mtg" => mtg::receive_command(&self, &ctx, command).await,
+                "ygo" => ygo::receive_command(self, &ctx, command).await,
+                "mtg" => mtg::receive_command(self, &ctx, command).await,
+                "daily" => daily::receive_command(self, &ctx, command).await,
                 "help" => self.command_help(&ctx, &command).await,
+                "list" => self.command_list(&ctx, &command).await,
+                "preferences" => preferences::receive_command(self, &ctx, &command).await,
                 _ => self.command_general(&ctx, &command).await,
             };
         }
     }
 
+    /// 接続時に参加中の全サーバーについて、またサーバーに参加した時に呼ばれる
+    async fn guild_create(&self, ctx: Context, guild: Guild, _is_new: Option<bool>) {
+        info!("Guild available: {} ({})", guild.name, guild.id);
+
+        // グローバルコマンドは `ready` で登録済み
+        if let CommandScope::Guilds(guild_ids) = &self.command_scope {
+            if guild_ids.contains(&guild.id) {
+                register_guild_commands(&ctx, &guild.id).await;
+            }
+        }
+    }
+
     async fn ready(&self, ctx: Context, ready: Ready) {
         info!("{} is connected!", ready.user.name);
 
-        let guild_id = GuildId::new(self.guild_id.parse().unwrap());
-
-        let commands = guild_id
-            .set_commands(
-                &ctx.http,
-                vec![CreateCommand::new("quiz")
-                    .description("Communicate with quiz bot")
-                    .add_option(
-                        CreateCommandOption::new(
-                            serenity::all::CommandOptionType::SubCommand,
-                            "ans",
-                            "Answer to quiz",
-                        )
-                        .add_sub_option(
-                            CreateCommandOption::new(
-                                serenity::all::CommandOptionType::String,
-                                "answer",
-                                "The answer",
-                            )
-                            .required(true),
-                        ),
-                    )
-                    .add_option(CreateCommandOption::new(
-                        serenity::all::CommandOptionType::SubCommand,
-                        "giveup",
-                        "Giveup quiz",
-                    ))
-                    .add_option(CreateCommandOption::new(
-                        serenity::all::CommandOptionType::SubCommand,
-                        "help",
-                        "Help of quiz bot",
-                    ))
-                    .add_option(ygo::create_subcommand(CreateCommandOption::new(
-                        serenity::all::CommandOptionType::SubCommandGroup,
-                        "ygo",
-                        "",
-                    )))
-                    .add_option(mtg::create_subcommand(CreateCommandOption::new(
-                        serenity::all::CommandOptionType::SubCommandGroup,
-                        "mtg",
-                        "",
-                    )))],
-            )
-            .await
-            .unwrap();
+        // サーバーごとに登録する場合は `guild_create` で登録する
+        if let CommandScope::Global = &self.command_scope {
+            match Command::set_global_commands(&ctx.http, create_commands()).await {
+                Ok(commands) => info!("Registered {} global commands", commands.len()),
+                Err(err) => error!("{}", err),
+            }
+        }
 
-        info!("Registered commands: {:#?}", commands);
+        if !self.tasks_started.swap(true, Ordering::SeqCst) {
+            timer::reschedule_all(&self.database, &ctx.http, &self.http_client).await;
+            schedule::start(self.database.clone(), self.http_client.clone(), ctx.clone());
+        }
     }
 }
 
@@ -174,24 +379,71 @@ async fn serenity(
     let token = secret_store
         .get("DISCORD_TOKEN")
         .context("'DISCORD_TOKEN' was not found")?;
-    let guild_id = secret_store
-        .get("GUILD_ID")
-        .context("'GUILD_ID' was not found")?;
+    // `GUILD_IDS`（カンマ区切り、以前の `GUILD_ID` も可）があればそのサーバーにだけ登録し、
+    // なければグローバルに登録する。ID に 0 は使えない
+    let command_scope = match secret_store
+        .get("GUILD_IDS")
+        .or_else(|| secret_store.get("GUILD_ID"))
+    {
+        Some(ids) => CommandScope::Guilds(
+            ids.split(',')
+                .map(|id| id.trim().parse::<NonZeroU64>().map(GuildId::from))
+                .collect::<Result<_, _>>()
+                .context("'GUILD_IDS' must be a comma-separated list of guild ids")?,
+        ),
+        None => CommandScope::Global,
+    };
+
+    // 放置されたクイズを片付けるまでの時間（既定は 1 週間）
+    let quiz_max_age_hours: u64 = match secret_store.get("QUIZ_MAX_AGE_HOURS") {
+        Some(hours) => hours
+            .parse()
+            .context("'QUIZ_MAX_AGE_HOURS' must be a number of hours")?,
+        None => 24 * 7,
+    };
+
+    // カードテキストを画像にするための日本語フォント
+    let font_path = secret_store
+        .get("QUIZ_FONT_PATH")
+        .unwrap_or_else(|| text_image::DEFAULT_FONT_PATH.to_owned());
+    if let Err(err) = text_image::load_font(&font_path) {
+        error!("Card text images are disabled: {}", err);
+    }
 
     pool.execute(include_str!("../schema.sql"))
         .await
         .context("failed to run migrations")?;
 
+    let private_users = PrivateUsers::load(&pool)
+        .await
+        .context("failed to load user preferences")?;
+
+    let http_client = HttpClient::new()
+        .map_err(anyhow::Error::msg)
+        .context("failed to build the HTTP client")?;
+
     // Set gateway intents, which decides what events the bot will be notified about
-    let intents = GatewayIntents::GUILD_MESSAGES | GatewayIntents::MESSAGE_CONTENT;
+    let intents =
+        GatewayIntents::GUILDS | GatewayIntents::GUILD_MESSAGES | GatewayIntents::MESSAGE_CONTENT;
 
     let client = Client::builder(&token, intents)
         .event_handler(Bot {
-            database: pool,
-            guild_id,
+            database: pool.clone(),
+            http_client,
+            ygo_cards: Prefetcher::new(),
+            mtg_cards: Prefetcher::new(),
+            command_scope,
+            private_users,
+            tasks_started: AtomicBool::new(false),
         })
         .await
         .expect("Err creating client");
 
+    sweeper::start(
+        pool,
+        client.http.clone(),
+        std::time::Duration::from_secs(quiz_max_age_hours * 60 * 60),
+    );
+
     Ok(SerenityService(client))
 }
@@ -0,0 +1,85 @@
+use serenity::{
+    all::{Message, ReactionType},
+    client::Context,
+};
+use tracing::{error, info};
+
+use crate::action;
+use crate::db::find_channel_quiz;
+use crate::reply::Reply;
+use crate::thread::archive_if_finished;
+use crate::Bot;
+
+/// スラッシュコマンドを使わない、普通の書き込みによる回答を受け付ける
+/// 早押しクイズのチャンネルでは全員の、クイズ用のスレッドでは出題された本人の書き込みが対象
+pub(crate) async fn receive_message(bot: &Bot, ctx: &Context, message: &Message) {
+    if message.author.bot || message.guild_id.is_none() {
+        return;
+    }
+    let answer = message.content.trim();
+    if answer.is_empty() {
+        return;
+    }
+
+    let quiz = match find_channel_quiz(&bot.database, &message.channel_id.into()).await {
+        Ok(Some(quiz)) => quiz,
+        Ok(None) => return,
+        Err(err) => {
+            error!("{}", err);
+            return;
+        }
+    };
+    if quiz
+        .user_id
+        .is_some_and(|user_id| user_id != i64::from(message.author.id))
+    {
+        return;
+    }
+    info!("Answered in {} by {}", message.channel_id, message.author);
+
+    // 早押しクイズでは会話の流れを邪魔しないよう、正解した時だけ発表する
+    // 外れた書き込みは誤答として数えず、ヒントも進めない
+    if quiz.user_id.is_none() {
+        match action::check_answer(bot, &quiz, answer).await {
+            Ok(true) => {}
+            Ok(false) => {
+                react(ctx, message, "❌").await;
+                return;
+            }
+            Err(err) => {
+                error!("{}", err);
+                return;
+            }
+        }
+    }
+
+    let (reply, solved) = match action::answer(bot, ctx, &message.author, &quiz, answer).await {
+        Ok(answered) => answered,
+        Err(msg) => {
+            error!(msg);
+            (Reply::text(msg), false)
+        }
+    };
+
+    if quiz.user_id.is_none() {
+        // 確かめてから回答するまでの間に、他の人が先に正解していることがある
+        react(ctx, message, if solved { "✅" } else { "❌" }).await;
+        if !solved {
+            return;
+        }
+    }
+
+    if let Err(err) = reply.send_message(&ctx.http, message.channel_id).await {
+        error!("{}", err)
+    }
+    archive_if_finished(bot, &ctx.http, &quiz).await;
+}
+
+async fn react(ctx: &Context, message: &Message, emoji: &str) {
+    if let Err(err) = message
+        .react(&ctx.http, ReactionType::Unicode(emoji.to_owned()))
+        .await
+    {
+        error!("{}", err)
+    }
+}
@@ -1,20 +1,58 @@
+pub mod api;
 pub mod db;
 
 use std::collections::HashMap;
 
-use reqwest::Client;
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
 use serenity::{
-    all::{CommandDataOption, CommandDataOptionValue, CommandInteraction, Mentionable},
-    builder::{CreateCommandOption, CreateInteractionResponse, CreateInteractionResponseMessage},
+    all::{
+        Colour, CommandDataOption, CommandDataOptionValue, CommandInteraction, CreateActionRow,
+        CreateButton, CreateEmbed, Mentionable, User,
+    },
+    builder::CreateCommandOption,
     client::Context,
 };
-use tracing::{error, info};
+use sqlx::PgPool;
+use tracing::info;
 
+use crate::http::HttpClient;
 use crate::Bot;
 
-use crate::common::roughly_card_name_equal;
+use crate::blank::{self, NO_BLANK};
+use crate::buttons::{disable_buttons, prompt_sent, quiz_buttons};
+use crate::channels::check_command_channel;
+use crate::common::{
+    create_mode_option, create_time_limit_option, mask_card_name, partial_segments, prompt_embed,
+    roughly_card_name_equal, score_message, DrawError, MAX_DRAWS, NO_USABLE_CARD, QUIZ_ENDED,
+};
+use crate::daily::ALREADY_ENTERED;
+use crate::db::{
+    finish_quiz, get_quiz_by_id, is_skipped_card, record_skipped_card, QuizMode, QuizOutcome,
+    QuizParams, QuizType,
+};
 use crate::mtg::db::{get_quiz, new_quiz};
+use crate::preferences::db::get_preferences;
+use crate::preferences::{create_private_option, wants_private};
+use crate::reply::{Deferred, Reply};
+use crate::settings::db::{get_settings, GuildSettings};
+use crate::text_image::{self, create_image_option};
+use crate::thread::{create_thread_option, wants_thread, PRIVATE_THREAD_ERROR};
+
+/// 埋め込みの色
+const EMBED_COLOUR: Colour = Colour::new(0x2E7D32);
+
+/// 出題に使う Magic のカードの情報
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Card {
+    pub scryfall_uri: String,
+    pub card_name: String,
+    pub english_name: String,
+    pub card_text: String,
+    pub flavor_text: Option<String>,
+    pub image_uri: String,
+}
 
 pub(crate) fn create_subcommand(c: CreateCommandOption) -> CreateCommandOption {
     c.description("Communicate with Magic:the Gathering! quiz bot")
@@ -24,26 +62,47 @@ pub(crate) fn create_subcommand(c: CreateCommandOption) -> CreateCommandOption {
                 "new",
                 "Start Magic:the Gathering quiz",
             )
-            .add_sub_option(
-                CreateCommandOption::new(
-                    serenity::all::CommandOptionType::String,
-                    "format",
-                    "The format (question range)",
-                )
-                .add_string_choice("スタンダード", "standard")
-                .add_string_choice("パイオニア", "pioneer")
-                .add_string_choice("モダン", "modern")
-                .add_string_choice("エターナル", "eternal")
-                .required(true),
-            )
+            .add_sub_option(create_format_option(
+                "The format (question range). Defaults to the server setting",
+            ))
             .add_sub_option(CreateCommandOption::new(
                 serenity::all::CommandOptionType::Boolean,
                 "rare",
                 "If true, only rare cards will be selected",
-            )),
+            ))
+            .add_sub_option(create_mode_option())
+            .add_sub_option(create_time_limit_option())
+            .add_sub_option(create_private_option(
+                "If true, the quiz is shown only to you. Defaults to your preference",
+            ))
+            .add_sub_option(create_thread_option())
+            .add_sub_option(create_image_option()),
         )
 }
 
+/// フォーマット（出題範囲）の選択肢
+pub(crate) fn create_format_option(description: &str) -> CreateCommandOption {
+    CreateCommandOption::new(
+        serenity::all::CommandOptionType::String,
+        "format",
+        description,
+    )
+    .add_string_choice("スタンダード", "standard")
+    .add_string_choice("パイオニア", "pioneer")
+    .add_string_choice("モダン", "modern")
+    .add_string_choice("エターナル", "eternal")
+}
+
+/// フォーマットの表示名
+pub(crate) fn format_name(format: &str) -> &str {
+    match format {
+        "standard" => "スタンダード",
+        "pioneer" => "パイオニア",
+        "modern" => "モダン",
+        _ => "エターナル",
+    }
+}
+
 pub(crate) async fn receive_command(bot: &Bot, ctx: &Context, command: CommandInteraction) {
     let CommandDataOptionValue::SubCommandGroup(after_mtg) = &command.data.options[0].value else {
         //unreachable
@@ -52,36 +111,36 @@ pub(crate) async fn receive_command(bot: &Bot, ctx: &Context, command: CommandIn
     let subc = after_mtg[0].name.as_str();
 
     info!(subc);
-    let result = match subc {
-        "new" => command_new(bot, ctx, &command, &after_mtg[0]).await,
-        _ => Err(format!("Unknown Command: {}", subc)),
+    let private = wants_private(bot, command.user.id.into(), &after_mtg[0]);
+    let Some(deferred) = Deferred::start(&ctx.http, command.id, &command.token, private).await
+    else {
+        return;
     };
+    if let Err(refusal) = check_command_channel(&bot.database, &command).await {
+        deferred.respond(Err(refusal)).await;
+        return;
+    }
 
-    let msg = match result {
-        Ok(msg) => msg,
-        Err(msg) => {
-            error!(msg);
-            msg
-        }
+    let thread = wants_thread(&after_mtg[0]);
+
+    let result = match subc {
+        "new" if private && thread => Err(PRIVATE_THREAD_ERROR.to_owned()),
+        "new" => command_new(bot, ctx, &command, &after_mtg[0], private).await,
+        _ => Err(format!("Unknown Command: {}", subc)),
     };
 
-    command
-        .create_response(
-            &ctx.http,
-            CreateInteractionResponse::Message(
-                CreateInteractionResponseMessage::new().content(msg),
-            ),
-        )
-        .await
-        .unwrap();
+    if let Some((quiz, prompt)) = deferred.respond_with(result).await {
+        prompt_sent(bot, &ctx.http, &command.token, &quiz, &prompt, thread).await;
+    }
 }
 
 async fn command_new(
     bot: &Bot,
-    _: &Context,
+    ctx: &Context,
     command: &CommandInteraction,
     command_data_option: &CommandDataOption,
-) -> Result<String, String> {
+    private: bool,
+) -> Result<(crate::db::Quiz, Reply), String> {
     let CommandDataOptionValue::SubCommand(subopt) = &command_data_option.value else {
         //unreachable
         panic!()
@@ -91,160 +150,566 @@ async fn command_new(
         cmd_arg_map.insert(opt.name.as_str(), &opt.value);
     }
 
+    let settings = get_settings(&bot.database, command.guild_id.map(Into::into))
+        .await
+        .map_err(|e| e.to_string())?;
+    if !settings.is_enabled(QuizType::Mtg) {
+        return Err("このサーバーでは Magic のクイズは無効になっています".to_owned());
+    }
+    let preferences = get_preferences(&bot.database, &command.user.id.into())
+        .await
+        .map_err(|e| e.to_string())?;
+
     let format = cmd_arg_map
         .get("format")
-        .map(|res| res.as_str().unwrap())
-        .unwrap_or("");
+        .and_then(|res| res.as_str())
+        .unwrap_or(&settings.mtg_format);
     info!("format = {}", format);
     let rare_mode = cmd_arg_map
         .get("rare")
         .map(|res| res.as_bool().unwrap())
         .unwrap_or(false);
     info!("rare_mode = {}", rare_mode);
+    let params = QuizParams {
+        user_id: Some(command.user.id.into()),
+        channel_id: command.channel_id.into(),
+        guild_id: command.guild_id.map(Into::into),
+        quiz_mode: QuizMode::from_option(cmd_arg_map.get("mode").and_then(|res| res.as_str())),
+        time_limit: cmd_arg_map
+            .get("time_limit")
+            .and_then(|res| res.as_i64())
+            .or(settings.time_limit),
+        daily_date: None,
+        mask_card_name: settings.mask_card_name,
+        private,
+        concurrency: preferences.concurrency,
+        text_as_image: text_image::wants_image(subopt),
+    };
+    if params.text_as_image && !text_image::is_available() {
+        return Err(text_image::UNAVAILABLE.to_owned());
+    }
 
+    // レアリティを指定しない出題には、フォーマットごとに先読みしておいたカードを使う
+    let prefetched = if rare_mode {
+        None
+    } else {
+        let pool = bot.database.clone();
+        let client = bot.http_client.clone();
+        let (format, mode) = (format.to_owned(), params.quiz_mode);
+        bot.mtg_cards.take((format.clone(), mode), move || {
+            let (pool, client, format) = (pool.clone(), client.clone(), format.clone());
+            async move { fetch_card(&pool, &client, &format, false, mode).await }
+        })
+    };
+    let card = match prefetched {
+        Some(card) => card,
+        None => {
+            fetch_card(
+                &bot.database,
+                &bot.http_client,
+                format,
+                rare_mode,
+                params.quiz_mode,
+            )
+            .await?
+        }
+    };
+
+    let mut filters = vec![("フォーマット", format_name(format).to_owned())];
+    if rare_mode {
+        filters.push(("レアリティ", "レア以上".to_owned()));
+    }
+    start_quiz(
+        &bot.database,
+        &bot.http_client,
+        ctx,
+        &params,
+        &card,
+        "",
+        &filters,
+    )
+    .await
+}
+
+/// 条件に合うカードをランダムに 1 枚取得する
+/// 出題に使えないカードを引いた場合は、記録してから引き直す
+pub(crate) async fn fetch_card(
+    pool: &PgPool,
+    client: &HttpClient,
+    format: &str,
+    rare_mode: bool,
+    mode: QuizMode,
+) -> Result<Card, String> {
+    for _ in 0..MAX_DRAWS {
+        let card = draw_card(client, format, rare_mode, mode).await?;
+        if is_skipped_card(pool, QuizType::Mtg, &card.id)
+            .await
+            .map_err(|e| e.to_string())?
+        {
+            info!("Redraw: `{}` ({}) was skipped before", card.name, card.id);
+            continue;
+        }
+
+        match to_card(&card) {
+            Ok(usable) => match unsuitable_reason(&usable, mode) {
+                Some(reason) => info!("Redraw: `{}` ({}): {}", card.name, card.id, reason),
+                None => return Ok(usable),
+            },
+            Err(DrawError::Unusable(reason)) => {
+                info!("Skipped `{}` ({}): {}", card.name, card.id, reason);
+                record_skipped_card(pool, QuizType::Mtg, &card.id, &reason)
+                    .await
+                    .map_err(|e| e.to_string())?;
+            }
+            Err(DrawError::Failed(err)) => return Err(err),
+        }
+    }
+
+    Err(NO_USABLE_CARD.to_owned())
+}
+
+async fn draw_card(
+    client: &HttpClient,
+    format: &str,
+    rare_mode: bool,
+    mode: QuizMode,
+) -> Result<api::Card, String> {
     let query = [
         "lang:japanese",
         match format {
             "standard" => "f:standard",
             "pioneer" => "f:pioneer",
             "modern" => "f:modern",
-            "eternal" | _ => "",
+            _ => "",
         },
         if rare_mode { "r>=r" } else { "" },
+        if mode == QuizMode::Flavor {
+            "has:flavor"
+        } else {
+            ""
+        },
     ]
     .into_iter()
     .filter(|o| !o.is_empty())
     .collect::<Vec<_>>()
     .join(" ");
 
-    let client = Client::new();
-
-    let card: serde_json::Value = serde_json::from_str(
+    api::parse_card(
         &client
-            .get("https://api.scryfall.com/cards/random")
-            .header("Accept", "application/json")
-            .header("User-Agent", "ygo-quiz-bot/1.0")
-            .query(&json!({
-              "q": query
-            }))
-            .send()
-            .await
-            .map_err(|e| e.to_string())?
-            .text()
-            .await
-            .map_err(|e| e.to_string())?,
+            .get_text(
+                client
+                    .get("https://api.scryfall.com/cards/random")
+                    .header("Accept", "application/json")
+                    .query(&json!({
+                      "q": query
+                    })),
+            )
+            .await?,
     )
-    .map_err(|e| e.to_string())?;
-    let card_name = card
-        .get("printed_name")
-        .ok_or("API応答の解析失敗")?
-        .as_str()
-        .ok_or("API応答の解析失敗")?;
-
-    let card_text = card
-        .get("printed_text")
-        .ok_or("API応答の解析失敗")?
-        .as_str()
-        .ok_or("API応答の解析失敗")?
-        .replace(&card_name, "<カード名>");
-
-    let image_uri = card
-        .pointer("/image_uris/art_crop")
-        .and_then(|t| t.as_str())
-        .unwrap_or("");
-
-    let content = match new_quiz(
-        &bot.database,
-        &command.user.id.into(),
-        card.pointer("/scryfall_uri")
-            .ok_or("API応答の解析失敗")?
-            .as_str()
-            .ok_or("API応答の解析失敗")?,
-        card_name,
-        card.get("name")
-            .ok_or("API応答の解析失敗")?
-            .as_str()
-            .ok_or("API応答の解析失敗")?,
-        &card_text,
+}
+
+/// 日本語名がないカードは、どの出題形式でも使えない
+fn to_card(card: &api::Card) -> Result<Card, DrawError> {
+    Ok(Card {
+        scryfall_uri: card.scryfall_uri.clone(),
+        card_name: card
+            .japanese_name()
+            .ok_or_else(|| DrawError::Unusable("日本語名がありません".to_owned()))?
+            .to_owned(),
+        english_name: card.english_name().to_owned(),
+        // バニラのクリーチャーなどはテキストがない
+        card_text: card.japanese_text().unwrap_or("").to_owned(),
+        flavor_text: card.flavor().map(|t| t.to_owned()),
+        image_uri: card.art_crop().unwrap_or("").to_owned(),
+    })
+}
+
+/// この出題形式では出題できない理由。他の出題形式では使えることがあるので、記録はしない
+fn unsuitable_reason(card: &Card, mode: QuizMode) -> Option<&'static str> {
+    match mode {
+        QuizMode::Flavor if card.flavor_text.is_none() => Some("フレーバーテキストがありません"),
+        QuizMode::Flavor => None,
+        _ if card.card_text.is_empty() => Some("日本語のテキストがありません"),
+        QuizMode::Blank if blank::choose(&card.card_text, QuizType::Mtg).is_none() => {
+            Some("空欄にできる言葉がありません")
+        }
+        _ => None,
+    }
+}
+
+/// クイズを登録し、出題文を返す
+pub(crate) async fn start_quiz(
+    database: &PgPool,
+    client: &HttpClient,
+    ctx: &Context,
+    params: &QuizParams,
+    card: &Card,
+    prefix: &str,
+    filters: &[(&str, String)],
+) -> Result<(crate::db::Quiz, Reply), String> {
+    let segments = segments(&card.card_text);
+    let blank = match params.quiz_mode {
+        QuizMode::Blank => Some(blank::choose(&card.card_text, QuizType::Mtg).ok_or(NO_BLANK)?),
+        _ => None,
+    };
+    let prompt_text = match (params.quiz_mode, &card.flavor_text) {
+        (QuizMode::Flavor, Some(flavor_text)) => flavor_text.as_str(),
+        (QuizMode::Flavor, None) => Err("このカードにはフレーバーテキストがありません")?,
+        // 能力は 1 行に 1 つずつ書かれている
+        (QuizMode::Effect, _) => card
+            .card_text
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .collect::<Vec<_>>()
+            .choose(&mut rand::thread_rng())
+            .copied()
+            .unwrap_or(&card.card_text),
+        (QuizMode::Partial, _) => segments.first().unwrap_or(&card.card_text),
+        (QuizMode::Blank, _) => &blank.as_ref().ok_or(NO_BLANK)?.text,
+        (QuizMode::Text, _) => &card.card_text,
+    };
+    // 穴埋めではカード名を見せる
+    let prompt_text = if params.mask_card_name && blank.is_none() {
+        mask_card_name(prompt_text, &card.card_name)
+    } else {
+        prompt_text.to_owned()
+    };
+
+    let partial = params.quiz_mode == QuizMode::Partial;
+    let (quiz, replaced) = new_quiz(
+        database,
+        params,
+        card,
+        partial.then_some(segments.len() as i32),
+        blank.as_ref().map(|blank| blank.answer.as_str()),
     )
     .await
-    {
-        Ok(msg) => {
-            info!("{}", msg);
-            format!(
-                "次のカードテキストを持つ Magic のカードは？(`/quiz ans` で回答)\n\n{}\n{}",
-                card_text, image_uri
-            )
-        }
-        Err(err) => {
-            error!("{}", err);
-            format!("データベースでエラーが発生しました：{}", err)
-        }
+    .map_err(|e| format!("データベースでエラーが発生しました：{}", e))?
+    .ok_or(ALREADY_ENTERED)?;
+    info!(
+        "Start quiz about `{}` for `{:?}`",
+        card.card_name, params.user_id
+    );
+    crate::timer::schedule(database.clone(), ctx.http.clone(), client.clone(), &quiz);
+    for replaced in &replaced {
+        crate::buttons::retire(&ctx.http, replaced).await;
+    }
+
+    let filters = filters
+        .iter()
+        .cloned()
+        .chain(partial.then(|| ("表示中のテキスト", format!("1 / {}", segments.len()))))
+        .collect::<Vec<_>>();
+    let subject = match &blank {
+        Some(_) => card.card_name.as_str(),
+        None => "Magic のカード",
     };
+    let (embed, fallback) = prompt_embed(subject, params, &filters, &prompt_text);
+    let embed = embed.colour(EMBED_COLOUR);
 
-    Ok(content)
+    let reply = Reply {
+        content: prefix.to_owned(),
+        embeds: vec![if card.image_uri.is_empty() {
+            embed
+        } else {
+            embed.thumbnail(&card.image_uri)
+        }],
+        fallback: format!("{}\n{}", fallback, card.image_uri),
+        components: vec![quiz_buttons(&quiz, false)],
+        ..Default::default()
+    };
+    let reply = if params.text_as_image {
+        reply.with_text_image(&prompt_text, QuizType::Mtg)?
+    } else {
+        reply
+    };
+
+    Ok((quiz, reply))
 }
 
-pub async fn command_ans(
-    bot: &Bot,
-    _: &Context,
-    command: &CommandInteraction,
-) -> Result<String, String> {
-    let CommandDataOptionValue::SubCommand(subopt) = &command.data.options[0].value else {
-        //unreachable
-        panic!()
+/// テキストを少しずつ見せる出題での区切り。能力は 1 行に 1 つずつ書かれている
+fn segments(card_text: &str) -> Vec<String> {
+    partial_segments(
+        card_text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(str::to_owned)
+            .collect(),
+    )
+}
+
+/// 正解を発表する埋め込み
+pub(crate) async fn reveal(pool: &sqlx::PgPool, quiz_id: &i64) -> Result<Reply, String> {
+    let quiz = get_quiz(pool, quiz_id).await.map_err(|e| e.to_string())?;
+    let blank_answer = get_quiz_by_id(pool, quiz_id)
+        .await
+        .map_err(|e| e.to_string())?
+        .blank_answer;
+
+    let text = match &quiz.flavor_text {
+        Some(flavor_text) if quiz.card_text.is_empty() => format!("*{}*", flavor_text),
+        Some(flavor_text) => format!("{}\n\n*{}*", quiz.card_text, flavor_text),
+        None => quiz.card_text.clone(),
+    };
+    let embed = CreateEmbed::new()
+        .title(format!("正解：{}", quiz.card_name))
+        .url(&quiz.scryfall_uri)
+        .colour(EMBED_COLOUR)
+        .field("英語名", &quiz.english_name, false)
+        .description(text);
+    let embed = match &blank_answer {
+        Some(answer) => embed.field("空欄に入る言葉", answer, false),
+        None => embed,
     };
-    let card_name = subopt[0].value.as_str().unwrap();
+    let embed = match quiz.image_uri.as_deref() {
+        Some(image_uri) if !image_uri.is_empty() => embed.image(image_uri),
+        _ => embed,
+    };
+
+    Ok(Reply {
+        embeds: vec![embed],
+        fallback: match &blank_answer {
+            Some(answer) => format!(
+                "空欄に入る言葉は「{}」でした（「{}」）\n {}",
+                answer, quiz.card_name, quiz.scryfall_uri
+            ),
+            None => format!(
+                "正解は「{}」（{}）でした \n {}",
+                quiz.card_name, quiz.english_name, quiz.scryfall_uri
+            ),
+        },
+        components: vec![CreateActionRow::Buttons(vec![CreateButton::new_link(
+            &quiz.scryfall_uri,
+        )
+        .label("Scryfall")])],
+        ..Default::default()
+    })
+}
 
+/// 回答が正解か。穴埋めでは空欄に入る言葉と、それ以外ではカード名と比べる
+fn is_correct(
+    quiz: &db::Quiz,
+    common_quiz: &crate::db::Quiz,
+    settings: &GuildSettings,
+    input: &str,
+) -> bool {
+    match &common_quiz.blank_answer {
+        Some(answer) => blank::is_correct(input, answer),
+        None => roughly_card_name_equal(
+            input,
+            &quiz.card_name,
+            settings
+                .accept_alternate_name
+                .then_some(quiz.english_name.as_str()),
+        ),
+    }
+}
+
+/// 正解かどうかだけを確かめる。誤答を記録せず、ヒントも用意しない
+/// 早押しクイズのチャンネルへの普通の書き込みは、ほとんどが回答ではないので、まずこちらで確かめる
+pub(crate) async fn check_answer(
+    bot: &Bot,
+    common_quiz: &crate::db::Quiz,
+    input: &str,
+) -> Result<bool, String> {
+    let quiz = get_quiz(&bot.database, &common_quiz.id)
+        .await
+        .map_err(|e| e.to_string())?;
+    let settings = get_settings(&bot.database, common_quiz.guild_id)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(is_correct(&quiz, common_quiz, &settings, input))
+}
+
+pub async fn command_ans(
+    bot: &Bot,
+    ctx: &Context,
+    user: &User,
+    card_name: &str,
+    common_quiz: &crate::db::Quiz,
+) -> Result<(Reply, bool), String> {
     info!("Answered: {}", card_name);
-    let content = match get_quiz(&bot.database, &command.user.id.into()).await {
+    let content = match get_quiz(&bot.database, &common_quiz.id).await {
         Ok(quiz) => {
-            if roughly_card_name_equal(card_name, &quiz.card_name, &quiz.english_name) {
-                let _ = crate::db::delete_quiz(&bot.database, &command.user.id.into()).await;
+            let settings = get_settings(&bot.database, common_quiz.guild_id)
+                .await
+                .map_err(|e| e.to_string())?;
+            if is_correct(&quiz, common_quiz, &settings, card_name) {
+                // 答えは削除する前に取っておく
+                let answer = reveal(&bot.database, &common_quiz.id).await?;
+                // 先に正解した人がいれば、このクイズは既に終わっている
+                let Some(quiz) = finish_quiz(
+                    &bot.database,
+                    &common_quiz.id,
+                    QuizOutcome::Correct,
+                    Some(user.id.into()),
+                )
+                .await
+                .map_err(|e| format!("データベースでエラーが発生しました：{}", e))?
+                else {
+                    return Ok((Reply::text(QUIZ_ENDED), false));
+                };
+                disable_buttons(&ctx.http, &quiz).await;
 
-                format!(
-                    "{}の回答：{}\n\n正解！ \n {}",
-                    command.user.mention(),
+                let answer = answer.with_content(format!(
+                    "{}の回答：{}\n\n正解！{}",
+                    user.mention(),
                     card_name,
-                    quiz.scryfall_uri
-                )
+                    score_message(&quiz)
+                ));
+                return Ok((answer, true));
             } else {
-                format!(
+                let attempted = crate::db::add_attempt(&bot.database, &common_quiz.id)
+                    .await
+                    .map_err(|e| e.to_string())?;
+
+                let reply = Reply::text(format!(
                     "{}の回答：{}\n\n不正解...",
-                    command.user.mention(),
+                    user.mention(),
                     card_name
-                )
+                ));
+                if attempted.quiz_mode == QuizMode::Partial {
+                    reply.with_next_segment(
+                        &attempted,
+                        &segments(&quiz.card_text),
+                        &quiz.card_name,
+                    )?
+                } else {
+                    reply
+                }
             }
         }
-        Err(err) => format!(
+        // 読み込む間に、他の人が先に正解した
+        Err(sqlx::Error::RowNotFound) => Reply::text(QUIZ_ENDED),
+        Err(err) => Reply::text(format!(
             "データベースでエラーが発生しました (`/quiz new` は実行しましたか？) : {}",
-            err.to_string()
-        ),
+            err
+        )),
     };
 
-    Ok(content)
+    Ok((content, false))
 }
 
-pub async fn command_giveup(
+pub async fn command_hint(
     bot: &Bot,
     _: &Context,
-    command: &CommandInteraction,
-) -> Result<String, String> {
-    info!("Giveup: {}", command.user);
-    let content = match get_quiz(&bot.database, &command.user.id.into()).await {
+    user: &User,
+    common_quiz: &crate::db::Quiz,
+) -> Result<Reply, String> {
+    info!("Hint: {}", user);
+    if common_quiz.quiz_mode == QuizMode::Blank {
+        let hinted = crate::db::add_hint(&bot.database, &common_quiz.id)
+            .await
+            .map_err(|e| e.to_string())?;
+        let answer = hinted.blank_answer.as_deref().unwrap_or_default();
+        return Ok(Reply::text(blank::hint(answer, hinted.reveal_level())));
+    }
+    // 他はテキストを少しずつ見せる出題でだけ、続きのテキストをヒントにする
+    if common_quiz.quiz_mode != QuizMode::Partial {
+        return Ok(Reply::text("Magic のクイズにはヒントがありません"));
+    }
+
+    let content = match get_quiz(&bot.database, &common_quiz.id).await {
         Ok(quiz) => {
-            let _ = crate::db::delete_quiz(&bot.database, &command.user.id.into()).await;
+            let hinted = crate::db::add_hint(&bot.database, &common_quiz.id)
+                .await
+                .map_err(|e| e.to_string())?;
+            let segments = segments(&quiz.card_text);
 
-            format!(
-                "正解は「{}」（{}）でした \n {}",
-                quiz.card_name, quiz.english_name, quiz.scryfall_uri
+            let reply = if hinted.reveal_level() < segments.len() {
+                Reply::text("ヒント：テキストの続きを表示します")
+            } else {
+                Reply::text("ヒント：テキストは全て表示しています")
+            };
+            reply.with_next_segment(&hinted, &segments, &quiz.card_name)?
+        }
+        Err(err) => Reply::text(format!(
+            "データベースでエラーが発生しました (`/quiz mtg new` は実行しましたか？) : {}",
+            err
+        )),
+    };
+
+    Ok(content)
+}
+
+pub async fn command_giveup(
+    bot: &Bot,
+    ctx: &Context,
+    user: &User,
+    common_quiz: &crate::db::Quiz,
+) -> Result<Reply, String> {
+    info!("Giveup: {}", user);
+    let content = match reveal(&bot.database, &common_quiz.id).await {
+        Ok(content) => {
+            if let Ok(Some(quiz)) = finish_quiz(
+                &bot.database,
+                &common_quiz.id,
+                QuizOutcome::Giveup,
+                Some(user.id.into()),
             )
+            .await
+            {
+                disable_buttons(&ctx.http, &quiz).await;
+            }
+
+            content
         }
-        Err(err) => format!(
+        Err(err) => Reply::text(format!(
             "データベースでエラーが発生しました (`/quiz mtg new` は実行しましたか？) : {}",
-            err.to_string()
-        ),
+            err
+        )),
     };
 
     Ok(content)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// ルール・テキストのないバニラのクリーチャー
+    const VANILLA: &str = r#"{
+        "object": "card",
+        "id": "x",
+        "name": "Grizzly Bears",
+        "printed_name": "灰色熊",
+        "flavor_text": "森を行く者は熊に気をつけよ。",
+        "scryfall_uri": "https://scryfall.com/card/x"
+    }"#;
+
+    #[test]
+    fn skip_cards_only_in_the_modes_they_do_not_suit() {
+        // テキストのないカードは、テキストを出す形式では引き直すが、記録はしない
+        let Ok(vanilla) = to_card(&api::parse_card(VANILLA).unwrap()) else {
+            panic!("a card with a Japanese name should be usable");
+        };
+        assert_eq!(vanilla.card_text, "");
+        assert_eq!(
+            unsuitable_reason(&vanilla, QuizMode::Text),
+            Some("日本語のテキストがありません")
+        );
+        assert_eq!(unsuitable_reason(&vanilla, QuizMode::Flavor), None);
+
+        // フレーバーテキストのないカードは、フレーバーテキストの出題でだけ引き直す
+        let card = Card {
+            card_text: "飛行".to_owned(),
+            flavor_text: None,
+            ..vanilla
+        };
+        assert_eq!(
+            unsuitable_reason(&card, QuizMode::Flavor),
+            Some("フレーバーテキストがありません")
+        );
+        assert_eq!(unsuitable_reason(&card, QuizMode::Text), None);
+        assert_eq!(unsuitable_reason(&card, QuizMode::Blank), None);
+    }
+
+    #[test]
+    fn unusable_without_japanese_name() {
+        let card = api::parse_card(&VANILLA.replace(r#""printed_name": "灰色熊","#, "")).unwrap();
+
+        assert!(matches!(to_card(&card), Err(DrawError::Unusable(_))));
+    }
+}
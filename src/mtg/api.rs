@@ -0,0 +1,206 @@
+//! Scryfall の API の応答
+//! https://scryfall.com/docs/api/cards
+
+use serde::Deserialize;
+
+/// Scryfall は応答の種類を `object` で示す
+#[derive(Deserialize)]
+#[serde(tag = "object", rename_all = "snake_case")]
+pub(crate) enum Response {
+    Card(Box<Card>),
+    Error(ApiError),
+}
+
+#[derive(Deserialize)]
+pub(crate) struct ApiError {
+    pub code: String,
+    pub details: String,
+}
+
+/// 出題に使わない項目は、形が変わっても解析に失敗しないよう省略可能にしておく
+#[derive(Deserialize)]
+pub(crate) struct Card {
+    pub id: String,
+    /// 英語名。両面カードなどでは `A // B` の形になる
+    pub name: String,
+    /// 英語以外の版の名前。両面カードなどでは `card_faces` の方にある
+    pub printed_name: Option<String>,
+    pub printed_text: Option<String>,
+    pub flavor_text: Option<String>,
+    pub scryfall_uri: String,
+    /// 面ごとに画像がある両面カードでは `card_faces` の方にある
+    pub image_uris: Option<ImageUris>,
+    #[serde(default)]
+    pub card_faces: Vec<CardFace>,
+    /// フォーマットごとの使用可否。出題の範囲は検索条件で絞るので、応答の形として読んでおくだけ
+    #[allow(dead_code)]
+    #[serde(default)]
+    pub legalities: Legalities,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct CardFace {
+    pub name: String,
+    pub printed_name: Option<String>,
+    pub printed_text: Option<String>,
+    pub flavor_text: Option<String>,
+    pub image_uris: Option<ImageUris>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct ImageUris {
+    pub art_crop: String,
+}
+
+#[allow(dead_code)]
+#[derive(Deserialize, Default)]
+pub(crate) struct Legalities {
+    pub standard: Option<Legality>,
+    pub pioneer: Option<Legality>,
+    pub modern: Option<Legality>,
+    pub legacy: Option<Legality>,
+    pub vintage: Option<Legality>,
+    pub pauper: Option<Legality>,
+    pub commander: Option<Legality>,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Legality {
+    Legal,
+    NotLegal,
+    Restricted,
+    Banned,
+    /// 今後増えるかもしれない値
+    #[serde(other)]
+    Unknown,
+}
+
+/// カードの応答を解析する。Scryfall がエラーを返した場合はその内容を返す
+pub(crate) fn parse_card(body: &str) -> Result<Card, String> {
+    match serde_json::from_str(body)
+        .map_err(|e| format!("Scryfall の応答を解析できません：{}", e))?
+    {
+        Response::Card(card) => Ok(*card),
+        Response::Error(error) => Err(format!(
+            "Scryfall でエラーが発生しました：{} ({})",
+            error.details, error.code
+        )),
+    }
+}
+
+impl Card {
+    /// 両面カードなどの表の面
+    fn front(&self) -> Option<&CardFace> {
+        self.card_faces.first()
+    }
+
+    /// 日本語名。両面カードなどでは表の面の名前
+    pub fn japanese_name(&self) -> Option<&str> {
+        self.printed_name
+            .as_deref()
+            .or_else(|| self.front()?.printed_name.as_deref())
+    }
+
+    /// 英語名。両面カードなどでは表の面の名前
+    pub fn english_name(&self) -> &str {
+        self.front().map_or(&self.name, |face| &face.name)
+    }
+
+    pub fn japanese_text(&self) -> Option<&str> {
+        self.printed_text
+            .as_deref()
+            .or_else(|| self.front()?.printed_text.as_deref())
+    }
+
+    pub fn flavor(&self) -> Option<&str> {
+        self.flavor_text
+            .as_deref()
+            .or_else(|| self.front()?.flavor_text.as_deref())
+    }
+
+    /// イラスト部分だけを切り抜いた画像
+    pub fn art_crop(&self) -> Option<&str> {
+        self.image_uris
+            .as_ref()
+            .or_else(|| self.front()?.image_uris.as_ref())
+            .map(|uris| uris.art_crop.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_japanese_card() {
+        let card = parse_card(include_str!("../../fixtures/scryfall/japanese_card.json")).unwrap();
+
+        assert_eq!(card.japanese_name(), Some("ラノワールのエルフ"));
+        assert_eq!(card.english_name(), "Llanowar Elves");
+        assert_eq!(
+            card.japanese_text(),
+            Some("{T}：あなたのマナ・プールに{G}を加える。")
+        );
+        assert!(card.flavor().unwrap().contains("ラノワール"));
+        assert_eq!(
+            card.art_crop(),
+            Some("https://cards.scryfall.io/art_crop/front/7/3/73542493-cd0b-4bb7-a5b8-8f889c76e4d6.jpg?1562302708")
+        );
+        assert_eq!(card.legalities.standard, Some(Legality::NotLegal));
+        assert_eq!(card.legalities.modern, Some(Legality::Legal));
+    }
+
+    #[test]
+    fn parse_double_faced_card() {
+        let card = parse_card(include_str!(
+            "../../fixtures/scryfall/double_faced_card.json"
+        ))
+        .unwrap();
+
+        assert!(card.printed_name.is_none());
+        assert!(card.image_uris.is_none());
+        assert_eq!(card.card_faces.len(), 2);
+        assert_eq!(card.japanese_name(), Some("秘密を掘り下げる者"));
+        assert_eq!(card.english_name(), "Delver of Secrets");
+        assert!(card
+            .japanese_text()
+            .unwrap()
+            .starts_with("あなたのアップキープの開始時に"));
+        assert_eq!(card.flavor(), None);
+        assert_eq!(
+            card.art_crop(),
+            Some("https://cards.scryfall.io/art_crop/front/2/8/28059d09-2c7d-4c61-af55-8942107a7c1f.jpg?1643588181")
+        );
+        assert_eq!(card.legalities.legacy, Some(Legality::Legal));
+        assert_eq!(card.legalities.vintage, Some(Legality::Legal));
+    }
+
+    #[test]
+    fn parse_error() {
+        let err = parse_card(include_str!("../../fixtures/scryfall/error.json"))
+            .map(|_| ())
+            .unwrap_err();
+        assert!(err.contains("not_found"), "{}", err);
+        assert!(err.contains("No cards found"), "{}", err);
+    }
+
+    #[test]
+    fn tolerate_changes_in_unused_fields() {
+        let card = parse_card(
+            r#"{"object":"card","id":"x","name":"x","scryfall_uri":"x","legalities":{"modern":"suspended"}}"#,
+        )
+        .unwrap();
+
+        assert_eq!(card.legalities.modern, Some(Legality::Unknown));
+        assert_eq!(card.legalities.standard, None);
+    }
+
+    #[test]
+    fn report_schema_mismatch() {
+        let err = parse_card(r#"{"object":"card","id":"x","lang":"ja","scryfall_uri":"x"}"#)
+            .map(|_| ())
+            .unwrap_err();
+        assert!(err.contains("missing field `name`"), "{}", err);
+    }
+}
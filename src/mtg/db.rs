@@ -1,64 +1,76 @@
 use sqlx::{FromRow, PgConnection, PgPool};
 
-use crate::db::insert_quiz;
+use crate::db::{insert_quiz, QuizParams};
+use crate::mtg::Card;
 
+#[allow(dead_code)]
 #[derive(FromRow)]
 pub struct Quiz {
-    pub user_id: i64,
+    pub quiz_id: i64,
     pub scryfall_uri: String,
     pub card_name: String,
     pub english_name: String,
     pub card_text: String,
+    pub flavor_text: Option<String>,
+    pub image_uri: Option<String>,
 }
 
+/// 今日の一枚に既に挑戦していれば `None` を返す
 pub(crate) async fn new_quiz(
     pool: &PgPool,
-    user_id: &i64,
-    scryfall_uri: &str,
-    card_name: &str,
-    english_name: &str,
-    card_text: &str,
-) -> Result<String, sqlx::Error> {
+    params: &QuizParams,
+    card: &Card,
+    segments: Option<i32>,
+    blank_answer: Option<&str>,
+) -> Result<Option<(crate::db::Quiz, Vec<crate::db::Quiz>)>, sqlx::Error> {
     let mut tx = pool.begin().await?;
 
-    crate::db::delete_tx_quiz(&mut tx, user_id).await?;
+    let Some((quiz, replaced)) = insert_quiz(
+        &mut tx,
+        params,
+        &crate::db::QuizType::Mtg,
+        segments,
+        blank_answer,
+    )
+    .await?
+    else {
+        return Ok(None);
+    };
 
     sqlx::query(
         r#"
-      INSERT INTO mtg_quiz (user_id, scryfall_uri, card_name, english_name, card_text)
-      VALUES ($1, $2, $3, $4, $5)
+      INSERT INTO mtg_quiz
+        (quiz_id, scryfall_uri, card_name, english_name, card_text, flavor_text, image_uri)
+      VALUES ($1, $2, $3, $4, $5, $6, $7)
     "#,
     )
-    .bind(&user_id)
-    .bind(&scryfall_uri)
-    .bind(&card_name)
-    .bind(&english_name)
-    .bind(&card_text)
+    .bind(quiz.id)
+    .bind(&card.scryfall_uri)
+    .bind(&card.card_name)
+    .bind(&card.english_name)
+    .bind(&card.card_text)
+    .bind(&card.flavor_text)
+    .bind(&card.image_uri)
     .execute(&mut *tx)
     .await?;
 
-    insert_quiz(&mut tx, user_id, &crate::db::QuizType::Mtg).await?;
-
     tx.commit().await?;
 
-    Ok(format!(
-        "Start quiz about `{}` for `{}`",
-        card_name, user_id
-    ))
+    Ok(Some((quiz, replaced)))
 }
 
-pub(crate) async fn get_quiz(pool: &PgPool, user_id: &i64) -> Result<Quiz, sqlx::Error> {
-    let data: Quiz = sqlx::query_as(r#"SELECT * FROM mtg_quiz WHERE user_id = $1"#)
-        .bind(user_id)
+pub(crate) async fn get_quiz(pool: &PgPool, quiz_id: &i64) -> Result<Quiz, sqlx::Error> {
+    let data: Quiz = sqlx::query_as(r#"SELECT * FROM mtg_quiz WHERE quiz_id = $1"#)
+        .bind(quiz_id)
         .fetch_one(pool)
         .await?;
 
     Ok(data)
 }
 
-pub(crate) async fn delete_quiz(pool: &mut PgConnection, user_id: &i64) -> Result<(), sqlx::Error> {
-    sqlx::query(r#"DELETE FROM mtg_quiz WHERE user_id = $1"#)
-        .bind(user_id)
+pub(crate) async fn delete_quiz(pool: &mut PgConnection, quiz_id: &i64) -> Result<(), sqlx::Error> {
+    sqlx::query(r#"DELETE FROM mtg_quiz WHERE quiz_id = $1"#)
+        .bind(quiz_id)
         .execute(pool)
         .await?;
 
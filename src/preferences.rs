@@ -0,0 +1,172 @@
+pub mod db;
+
+use std::collections::HashSet;
+use std::sync::RwLock;
+
+use serenity::{
+    all::{CommandDataOption, CommandDataOptionValue, CommandInteraction},
+    builder::{CreateCommandOption, CreateInteractionResponse, CreateInteractionResponseMessage},
+    client::Context,
+};
+use sqlx::PgPool;
+use tracing::{error, info};
+
+use crate::db::Concurrency;
+use crate::preferences::db::UserPreferences;
+use crate::Bot;
+
+pub(crate) fn create_subcommand(c: CreateCommandOption) -> CreateCommandOption {
+    c.description("Show or change your preferences")
+        .add_sub_option(create_private_option(
+            "If true, your quizzes are shown only to you by default",
+        ))
+        .add_sub_option(
+            CreateCommandOption::new(
+                serenity::all::CommandOptionType::String,
+                "concurrency",
+                "How many quizzes you can hold at once",
+            )
+            .add_string_choice("1 問だけ", "single")
+            .add_string_choice("ゲームごとに 1 問", "per_game")
+            .add_string_choice("チャンネルごとに 1 問", "per_channel"),
+        )
+}
+
+/// 各ゲームの `new` に共通する、本人にだけ表示するかの指定
+pub(crate) fn create_private_option(description: &str) -> CreateCommandOption {
+    CreateCommandOption::new(
+        serenity::all::CommandOptionType::Boolean,
+        "private",
+        description,
+    )
+}
+
+/// 本人にだけ表示する設定にしているユーザー
+/// 出題を遅延させる前に表示先を決める必要があるので、データベースを引かずに済むよう覚えておく
+pub(crate) struct PrivateUsers(RwLock<HashSet<i64>>);
+
+impl PrivateUsers {
+    /// 起動時に保存済みの設定から読み込む
+    pub async fn load(pool: &PgPool) -> Result<PrivateUsers, sqlx::Error> {
+        Ok(PrivateUsers(RwLock::new(
+            db::get_private_users(pool).await?.into_iter().collect(),
+        )))
+    }
+
+    pub fn contains(&self, user_id: i64) -> bool {
+        self.0.read().unwrap().contains(&user_id)
+    }
+
+    fn set(&self, user_id: i64, private: bool) {
+        let mut users = self.0.write().unwrap();
+        if private {
+            users.insert(user_id);
+        } else {
+            users.remove(&user_id);
+        }
+    }
+}
+
+/// `private` の指定があればそれに、なければユーザーの設定に従う
+pub(crate) fn wants_private(bot: &Bot, user_id: i64, subc: &CommandDataOption) -> bool {
+    if let CommandDataOptionValue::SubCommand(options) = &subc.value {
+        if let Some(private) = options.iter().find_map(|option| match option.value {
+            CommandDataOptionValue::Boolean(b) if option.name == "private" => Some(b),
+            _ => None,
+        }) {
+            return private;
+        }
+    }
+
+    bot.private_users.contains(user_id)
+}
+
+fn describe(preferences: &UserPreferences) -> String {
+    format!(
+        "- 本人にだけ表示 (private) : {}\n- 同時に挑戦できるクイズ (concurrency) : {}\n",
+        if preferences.private {
+            "有効"
+        } else {
+            "無効"
+        },
+        preferences.concurrency.name()
+    )
+}
+
+pub(crate) async fn receive_command(bot: &Bot, ctx: &Context, command: &CommandInteraction) {
+    let result = command_preferences(bot, command).await;
+
+    let msg = match result {
+        Ok(msg) => msg,
+        Err(msg) => {
+            error!(msg);
+            msg
+        }
+    };
+
+    if let Err(err) = command
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new()
+                    .content(msg)
+                    .ephemeral(true),
+            ),
+        )
+        .await
+    {
+        error!("{}", err)
+    }
+}
+
+async fn command_preferences(bot: &Bot, command: &CommandInteraction) -> Result<String, String> {
+    let CommandDataOptionValue::SubCommand(options) = &command.data.options[0].value else {
+        //unreachable
+        panic!()
+    };
+
+    let mut preferences = db::get_preferences(&bot.database, &command.user.id.into())
+        .await
+        .map_err(|e| e.to_string())?;
+    if options.is_empty() {
+        return Ok(format!("現在の設定\n{}", describe(&preferences)));
+    }
+
+    for option in options {
+        match (option.name.as_str(), &option.value) {
+            ("private", CommandDataOptionValue::Boolean(b)) => preferences.private = *b,
+            ("concurrency", CommandDataOptionValue::String(s)) => {
+                if let Some(concurrency) = Concurrency::from_option(s) {
+                    preferences.concurrency = concurrency
+                }
+            }
+            _ => {}
+        }
+    }
+
+    db::save_preferences(&bot.database, &preferences)
+        .await
+        .map_err(|e| e.to_string())?;
+    bot.private_users
+        .set(preferences.user_id, preferences.private);
+
+    info!("Updated preferences for {}", command.user);
+    Ok(format!("設定を変更しました\n{}", describe(&preferences)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remember_changed_private_preference() {
+        let users = PrivateUsers(RwLock::new(HashSet::from([1])));
+        assert!(users.contains(1));
+        assert!(!users.contains(2));
+
+        users.set(2, true);
+        users.set(1, false);
+        assert!(!users.contains(1));
+        assert!(users.contains(2));
+    }
+}
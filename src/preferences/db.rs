@@ -0,0 +1,63 @@
+use sqlx::{FromRow, PgPool};
+
+use crate::db::Concurrency;
+
+/// ユーザーごとの設定。未設定のユーザーは既定値を使う
+#[derive(FromRow)]
+pub struct UserPreferences {
+    pub user_id: i64,
+    /// 出題や回答を本人にだけ表示するか
+    pub private: bool,
+    pub concurrency: Concurrency,
+}
+
+impl UserPreferences {
+    fn default_for(user_id: i64) -> UserPreferences {
+        UserPreferences {
+            user_id,
+            private: false,
+            concurrency: Concurrency::Single,
+        }
+    }
+}
+
+pub(crate) async fn get_preferences(
+    pool: &PgPool,
+    user_id: &i64,
+) -> Result<UserPreferences, sqlx::Error> {
+    let data: Option<UserPreferences> =
+        sqlx::query_as(r#"SELECT * FROM user_preferences WHERE user_id = $1"#)
+            .bind(user_id)
+            .fetch_optional(pool)
+            .await?;
+
+    Ok(data.unwrap_or_else(|| UserPreferences::default_for(*user_id)))
+}
+
+/// 本人にだけ表示する設定にしているユーザーの一覧
+pub(crate) async fn get_private_users(pool: &PgPool) -> Result<Vec<i64>, sqlx::Error> {
+    sqlx::query_scalar(r#"SELECT user_id FROM user_preferences WHERE private"#)
+        .fetch_all(pool)
+        .await
+}
+
+pub(crate) async fn save_preferences(
+    pool: &PgPool,
+    preferences: &UserPreferences,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+      INSERT INTO user_preferences (user_id, private, concurrency)
+      VALUES ($1, $2, $3)
+      ON CONFLICT (user_id) DO UPDATE
+        SET private = EXCLUDED.private, concurrency = EXCLUDED.concurrency
+    "#,
+    )
+    .bind(preferences.user_id)
+    .bind(preferences.private)
+    .bind(preferences.concurrency)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
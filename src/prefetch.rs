@@ -0,0 +1,147 @@
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::sync::Notify;
+use tracing::{error, info};
+
+/// 条件ごとに用意しておくカードの枚数
+const BUFFER_SIZE: usize = 3;
+/// 取得に失敗した場合に、次に試すまでの待ち時間
+const RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// 出題に使うカードを条件ごとに先読みしておく
+/// 条件が初めて使われたときにその条件用の取得処理を始め、以降は常に数枚を用意しておく
+pub(crate) struct Prefetcher<K, T> {
+    buffers: Mutex<HashMap<K, Arc<Buffer<T>>>>,
+}
+
+struct Buffer<T> {
+    cards: Mutex<VecDeque<T>>,
+    /// カードが使われたことを取得処理に知らせる
+    taken: Notify,
+}
+
+impl<K, T> Prefetcher<K, T>
+where
+    K: Eq + Hash + Clone + Send + std::fmt::Debug + 'static,
+    T: Send + 'static,
+{
+    pub fn new() -> Prefetcher<K, T> {
+        Prefetcher {
+            buffers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 用意してあるカードを 1 枚取り出す。なければ `None` を返すので、呼び出し側で取得すること
+    /// `fetch` は、その条件の取得処理がまだ動いていなければ、それを始めるために使う
+    pub fn take<F, Fut>(&self, key: K, fetch: F) -> Option<T>
+    where
+        F: Fn() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<T, String>> + Send + 'static,
+    {
+        let buffer = self.buffer(key, fetch);
+        let card = buffer.cards.lock().unwrap().pop_front();
+        buffer.taken.notify_one();
+
+        card
+    }
+
+    fn buffer<F, Fut>(&self, key: K, fetch: F) -> Arc<Buffer<T>>
+    where
+        F: Fn() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<T, String>> + Send + 'static,
+    {
+        let mut buffers = self.buffers.lock().unwrap();
+        if let Some(buffer) = buffers.get(&key) {
+            return buffer.clone();
+        }
+
+        let buffer = Arc::new(Buffer {
+            cards: Mutex::new(VecDeque::new()),
+            taken: Notify::new(),
+        });
+        buffers.insert(key.clone(), buffer.clone());
+        tokio::spawn(refill(key, buffer.clone(), fetch));
+
+        buffer
+    }
+}
+
+/// 用意してあるカードが減るたびに補充する
+async fn refill<K, T, F, Fut>(key: K, buffer: Arc<Buffer<T>>, fetch: F)
+where
+    K: std::fmt::Debug,
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<T, String>>,
+{
+    info!("Started prefetching cards for {:?}", key);
+    loop {
+        if buffer.cards.lock().unwrap().len() >= BUFFER_SIZE {
+            buffer.taken.notified().await;
+            continue;
+        }
+
+        match fetch().await {
+            Ok(card) => buffer.cards.lock().unwrap().push_back(card),
+            Err(err) => {
+                error!("Failed to prefetch a card for {:?}: {}", key, err);
+                tokio::time::sleep(RETRY_INTERVAL).await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn buffered(prefetcher: &Prefetcher<&'static str, usize>, key: &str) -> usize {
+        prefetcher.buffers.lock().unwrap()[key]
+            .cards
+            .lock()
+            .unwrap()
+            .len()
+    }
+
+    /// 取得処理が追いついて、カードが揃うまで待つ
+    async fn settle(prefetcher: &Prefetcher<&'static str, usize>, key: &str) {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while buffered(prefetcher, key) < BUFFER_SIZE {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .expect("prefetching did not catch up");
+    }
+
+    #[tokio::test]
+    async fn serve_cards_after_the_first_request() {
+        let prefetcher = Prefetcher::new();
+        let count = Arc::new(AtomicUsize::new(0));
+        let fetch = {
+            let count = count.clone();
+            move || {
+                let n = count.fetch_add(1, Ordering::SeqCst);
+                async move { Ok(n) }
+            }
+        };
+
+        assert_eq!(prefetcher.take("ygo", fetch.clone()), None);
+        settle(&prefetcher, "ygo").await;
+        assert_eq!(count.load(Ordering::SeqCst), BUFFER_SIZE);
+
+        assert_eq!(prefetcher.take("ygo", fetch.clone()), Some(0));
+        assert_eq!(prefetcher.take("ygo", fetch.clone()), Some(1));
+        settle(&prefetcher, "ygo").await;
+        // 使われた分だけ補充される
+        assert_eq!(count.load(Ordering::SeqCst), BUFFER_SIZE + 2);
+
+        // 条件ごとに別々に用意する
+        assert_eq!(prefetcher.take("mtg", fetch), None);
+    }
+}
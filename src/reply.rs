@@ -0,0 +1,366 @@
+use serenity::all::{
+    Builder, ChannelId, CreateActionRow, CreateAttachment, CreateEmbed, CreateInteractionResponse,
+    CreateInteractionResponseFollowup, CreateInteractionResponseMessage, CreateMessage,
+    EditInteractionResponse, Http, InteractionId, Message, UserId,
+};
+use tracing::error;
+
+use crate::common::{mask_card_name, next_segment};
+use crate::db::{Quiz, QuizType};
+use crate::text_image;
+
+/// Discord のメッセージの本文の上限
+const MAX_CONTENT_LENGTH: usize = 2000;
+
+/// Discord に送る応答の内容
+/// 埋め込みを送れなかった場合は、埋め込みの代わりに `fallback` を本文に付けて送り直す
+#[derive(Clone, Default)]
+pub(crate) struct Reply {
+    /// 埋め込みと一緒に送る本文（回答者へのメンションなど）
+    pub content: String,
+    pub embeds: Vec<CreateEmbed>,
+    /// 埋め込みと同じ内容の、文字だけの表現
+    pub fallback: String,
+    pub attachments: Vec<CreateAttachment>,
+    pub components: Vec<CreateActionRow>,
+}
+
+impl Reply {
+    pub fn text(content: impl Into<String>) -> Reply {
+        Reply {
+            content: content.into(),
+            ..Default::default()
+        }
+    }
+
+    /// コマンドの処理に失敗したことを伝える
+    pub fn error(msg: impl std::fmt::Display) -> Reply {
+        Reply::text(format!("⚠️ {}", msg))
+    }
+
+    /// 埋め込みの前に本文を付け足す
+    pub fn with_content(self, content: impl Into<String>) -> Reply {
+        Reply {
+            content: content.into(),
+            ..self
+        }
+    }
+
+    /// カードテキストを描いた画像を添付する。埋め込みでの表示は `prompt_embed` が行う
+    pub fn with_text_image(mut self, text: &str, quiz_type: QuizType) -> Result<Reply, String> {
+        let png = text_image::render(text, quiz_type)?;
+        self.attachments
+            .push(CreateAttachment::bytes(png, text_image::FILE_NAME));
+
+        Ok(self)
+    }
+
+    /// テキストを少しずつ見せる出題で、誤答やヒントのたびに続きのテキストを付け足す
+    /// カード名を伏せるかや画像で添付するかは、出題した時に合わせる
+    pub fn with_next_segment(
+        mut self,
+        quiz: &Quiz,
+        segments: &[String],
+        card_name: &str,
+    ) -> Result<Reply, String> {
+        let Some((heading, segment)) = next_segment(segments, quiz.reveal_level()) else {
+            return Ok(self);
+        };
+        let segment = if quiz.mask_card_name {
+            mask_card_name(segment, card_name)
+        } else {
+            segment.to_owned()
+        };
+
+        if quiz.text_as_image {
+            self.content = format!("{}\n\n{}：", self.content, heading);
+            self.with_text_image(&segment, quiz.quiz_type)
+        } else {
+            self.content = format!("{}\n\n{}：\n{}", self.content, heading, segment);
+            Ok(self)
+        }
+    }
+
+    fn without_embeds(&self) -> Reply {
+        Reply {
+            content: [self.content.as_str(), self.fallback.as_str()]
+                .into_iter()
+                .filter(|s| !s.is_empty())
+                .collect::<Vec<_>>()
+                .join("\n\n"),
+            embeds: vec![],
+            fallback: "".to_owned(),
+            ..self.clone()
+        }
+    }
+
+    pub fn to_edit_response(&self) -> EditInteractionResponse {
+        self.attachments.iter().cloned().fold(
+            EditInteractionResponse::new()
+                .content(&self.content)
+                .embeds(self.embeds.clone())
+                .components(self.components.clone()),
+            |response, attachment| response.new_attachment(attachment),
+        )
+    }
+
+    pub fn to_message(&self) -> CreateMessage {
+        CreateMessage::new()
+            .content(&self.content)
+            .embeds(self.embeds.clone())
+            .components(self.components.clone())
+            .add_files(self.attachments.clone())
+    }
+
+    /// 長すぎる本文を複数のメッセージに分ける
+    /// 埋め込みや添付ファイル、ボタンは最初のメッセージに付ける
+    fn split(&self) -> Vec<Reply> {
+        let mut contents = split_content(&self.content).into_iter();
+        let first = Reply {
+            content: contents.next().unwrap_or_default(),
+            ..self.clone()
+        };
+
+        [first]
+            .into_iter()
+            .chain(contents.map(Reply::text))
+            .collect()
+    }
+
+    /// 最初のメッセージを返す
+    pub async fn send_message(
+        &self,
+        http: &Http,
+        channel_id: ChannelId,
+    ) -> Result<Message, serenity::Error> {
+        let mut parts = self.split();
+        let message = match channel_id.send_message(http, parts[0].to_message()).await {
+            Err(err) if !self.embeds.is_empty() => {
+                error!("Failed to send embeds, falling back to text: {}", err);
+                parts = self.without_embeds().split();
+                channel_id.send_message(http, parts[0].to_message()).await?
+            }
+            result => result?,
+        };
+        for part in &parts[1..] {
+            channel_id.send_message(http, part.to_message()).await?;
+        }
+
+        Ok(message)
+    }
+
+    pub async fn send_dm(&self, http: &Http, user_id: UserId) -> Result<Message, serenity::Error> {
+        let channel = user_id.create_dm_channel(http).await?;
+        self.send_message(http, channel.id).await
+    }
+}
+
+/// 遅延させたインタラクションへの応答
+/// 外部のサイトを使う処理は Discord の 3 秒の制限に間に合わないことがあるので、
+/// 処理を始める前に `start` で遅延させておき、結果は `respond` で送る
+pub(crate) struct Deferred<'a> {
+    http: &'a Http,
+    token: &'a str,
+    ephemeral: bool,
+}
+
+impl<'a> Deferred<'a> {
+    /// 遅延させられなければ、ログに残して `None` を返す
+    pub async fn start(
+        http: &'a Http,
+        interaction_id: InteractionId,
+        token: &'a str,
+        ephemeral: bool,
+    ) -> Option<Deferred<'a>> {
+        match CreateInteractionResponse::Defer(
+            CreateInteractionResponseMessage::new().ephemeral(ephemeral),
+        )
+        .execute(http, (interaction_id, token))
+        .await
+        {
+            Ok(()) => Some(Deferred {
+                http,
+                token,
+                ephemeral,
+            }),
+            Err(err) => {
+                error!("Failed to defer the response: {}", err);
+                None
+            }
+        }
+    }
+
+    /// 処理の結果を送る。エラーの場合はその内容を伝えて `None` を返す
+    pub async fn respond(&self, result: Result<Reply, String>) -> Option<Message> {
+        self.respond_with(result.map(|reply| ((), reply)))
+            .await
+            .map(|(_, message)| message)
+    }
+
+    /// `respond` と同じだが、送ったメッセージと合わせて処理の結果の値を返す
+    pub async fn respond_with<T>(
+        &self,
+        result: Result<(T, Reply), String>,
+    ) -> Option<(T, Message)> {
+        let (value, reply) = match result {
+            Ok(result) => result,
+            Err(msg) => {
+                error!(msg);
+                if let Err(err) = self.edit(&Reply::error(msg)).await {
+                    error!("{}", err)
+                }
+                return None;
+            }
+        };
+
+        match self.edit(&reply).await {
+            Ok(message) => Some((value, message)),
+            Err(err) => {
+                error!("{}", err);
+                None
+            }
+        }
+    }
+
+    /// 遅延させた応答を書き換える。本文が長すぎる場合、残りはフォローアップで送る
+    async fn edit(&self, reply: &Reply) -> Result<Message, serenity::Error> {
+        let mut parts = reply.split();
+        let message = match parts[0]
+            .to_edit_response()
+            .execute(self.http, self.token)
+            .await
+        {
+            Err(err) if !reply.embeds.is_empty() => {
+                error!("Failed to send embeds, falling back to text: {}", err);
+                parts = reply.without_embeds().split();
+                parts[0]
+                    .to_edit_response()
+                    .execute(self.http, self.token)
+                    .await?
+            }
+            result => result?,
+        };
+        for part in &parts[1..] {
+            CreateInteractionResponseFollowup::new()
+                .content(&part.content)
+                .ephemeral(self.ephemeral)
+                .execute(self.http, (None, self.token))
+                .await?;
+        }
+
+        Ok(message)
+    }
+}
+
+/// 本文を Discord の上限に収まるよう分ける。なるべく改行の位置で分ける
+fn split_content(content: &str) -> Vec<String> {
+    let mut parts = vec![];
+    let mut rest = content;
+    while rest.chars().count() > MAX_CONTENT_LENGTH {
+        let limit = rest
+            .char_indices()
+            .nth(MAX_CONTENT_LENGTH)
+            .map_or(rest.len(), |(i, _)| i);
+        let end = rest[..limit]
+            .rfind('\n')
+            .filter(|&i| i > 0)
+            .unwrap_or(limit);
+        parts.push(rest[..end].to_owned());
+        rest = rest[end..].strip_prefix('\n').unwrap_or(&rest[end..]);
+    }
+    parts.push(rest.to_owned());
+
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::QuizMode;
+
+    #[test]
+    fn keep_short_content() {
+        assert_eq!(split_content(""), vec![""]);
+        assert_eq!(split_content("正解！"), vec!["正解！"]);
+        let full = "あ".repeat(MAX_CONTENT_LENGTH);
+        assert_eq!(split_content(&full), vec![full]);
+    }
+
+    #[test]
+    fn split_at_line_breaks() {
+        let line = "い".repeat(900);
+        let content = [line.as_str(); 5].join("\n");
+
+        let parts = split_content(&content);
+        assert_eq!(parts.len(), 3);
+        assert_eq!(parts[0], [line.as_str(); 2].join("\n"));
+        assert_eq!(parts[2], line);
+        assert_eq!(parts.join("\n"), content);
+    }
+
+    #[test]
+    fn split_long_lines_by_characters() {
+        let content = "う".repeat(MAX_CONTENT_LENGTH * 2 + 10);
+
+        let parts = split_content(&content);
+        assert_eq!(
+            parts.iter().map(|p| p.chars().count()).collect::<Vec<_>>(),
+            vec![MAX_CONTENT_LENGTH, MAX_CONTENT_LENGTH, 10]
+        );
+        assert_eq!(parts.concat(), content);
+    }
+
+    #[test]
+    fn mask_next_segments_as_when_asked() {
+        let segments = [
+            "青眼の白龍を召喚する。".to_owned(),
+            "青眼の白龍を破壊する。".to_owned(),
+        ];
+        let quiz = Quiz {
+            quiz_mode: QuizMode::Partial,
+            attempts: 1,
+            ..crate::db::tests::quiz(QuizType::Ygo, 1, None)
+        };
+
+        let masked = Reply::text("不正解...")
+            .with_next_segment(&quiz, &segments, "青眼の白龍")
+            .unwrap();
+        assert!(
+            masked.content.ends_with("<カード名>を破壊する。"),
+            "{}",
+            masked.content
+        );
+
+        let unmasked = Reply::text("不正解...")
+            .with_next_segment(
+                &Quiz {
+                    mask_card_name: false,
+                    ..quiz
+                },
+                &segments,
+                "青眼の白龍",
+            )
+            .unwrap();
+        assert!(
+            unmasked.content.ends_with("青眼の白龍を破壊する。"),
+            "{}",
+            unmasked.content
+        );
+    }
+
+    #[test]
+    fn attach_everything_to_the_first_part() {
+        let reply = Reply {
+            content: "え".repeat(MAX_CONTENT_LENGTH + 1),
+            embeds: vec![CreateEmbed::new()],
+            fallback: "fallback".to_owned(),
+            ..Default::default()
+        };
+
+        let parts = reply.split();
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].embeds.len(), 1);
+        assert_eq!(parts[1].content, "え");
+        assert!(parts[1].embeds.is_empty());
+    }
+}
@@ -0,0 +1,341 @@
+pub mod db;
+
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use croner::Cron;
+use serenity::{
+    all::{ChannelId, ChannelType, CommandDataOption, CommandDataOptionValue},
+    builder::CreateCommandOption,
+    client::Context,
+};
+use sqlx::PgPool;
+use tracing::{error, info};
+
+use crate::common::{create_game_option, create_mode_option, create_time_limit_option, jst};
+use crate::db::{set_prompt, Concurrency, QuizMode, QuizParams, QuizType};
+use crate::http::HttpClient;
+use crate::schedule::db::{NewSchedule, Schedule};
+use crate::settings::db::get_settings;
+use crate::{mtg, ygo, Bot};
+
+/// 予定時刻を確認する間隔
+const TICK: Duration = Duration::from_secs(30);
+
+/// 停止中などで予定時刻を過ぎてしまった場合、これより遅れたものは投稿せずに飛ばす
+const MAX_DELAY: chrono::TimeDelta = chrono::TimeDelta::minutes(10);
+
+pub(crate) fn create_subcommand(c: CreateCommandOption) -> CreateCommandOption {
+    c.description("Post race quizzes on a schedule")
+        .add_sub_option(
+            CreateCommandOption::new(
+                serenity::all::CommandOptionType::SubCommand,
+                "add",
+                "Add a schedule",
+            )
+            .add_sub_option(
+                CreateCommandOption::new(
+                    serenity::all::CommandOptionType::Channel,
+                    "channel",
+                    "The channel to post quizzes",
+                )
+                .channel_types(vec![ChannelType::Text])
+                .required(true),
+            )
+            .add_sub_option(
+                CreateCommandOption::new(
+                    serenity::all::CommandOptionType::String,
+                    "cron",
+                    "When to post, in cron format (JST, e.g. `0 21 * * *`)",
+                )
+                .required(true),
+            )
+            .add_sub_option(create_game_option("The game").required(true))
+            .add_sub_option(CreateCommandOption::new(
+                serenity::all::CommandOptionType::String,
+                "fname",
+                "(Yu-gi-oh!) If specified, only cards with it in the card name will be asked",
+            ))
+            .add_sub_option(mtg::create_format_option(
+                "(Magic) The format (question range). Defaults to the server setting",
+            ))
+            .add_sub_option(CreateCommandOption::new(
+                serenity::all::CommandOptionType::Boolean,
+                "rare",
+                "(Magic) If true, only rare cards will be selected",
+            ))
+            .add_sub_option(create_mode_option())
+            .add_sub_option(create_time_limit_option()),
+        )
+        .add_sub_option(CreateCommandOption::new(
+            serenity::all::CommandOptionType::SubCommand,
+            "list",
+            "List schedules of this server",
+        ))
+        .add_sub_option(
+            CreateCommandOption::new(
+                serenity::all::CommandOptionType::SubCommand,
+                "remove",
+                "Remove a schedule",
+            )
+            .add_sub_option(
+                CreateCommandOption::new(
+                    serenity::all::CommandOptionType::Integer,
+                    "id",
+                    "The schedule id shown in `list`",
+                )
+                .required(true),
+            ),
+        )
+}
+
+pub(crate) async fn receive_command(
+    bot: &Bot,
+    guild_id: i64,
+    subc: &CommandDataOption,
+) -> Result<String, String> {
+    let CommandDataOptionValue::SubCommand(options) = &subc.value else {
+        //unreachable
+        panic!()
+    };
+
+    info!(subc.name);
+    match subc.name.as_str() {
+        "add" => command_add(bot, guild_id, options).await,
+        "list" => command_list(bot, guild_id).await,
+        "remove" => command_remove(bot, guild_id, options).await,
+        _ => Err(format!("Unknown Command: {}", subc.name)),
+    }
+}
+
+/// cron 式を日本時間で解釈し、`after` より後の次の予定時刻を返す
+pub(crate) fn next_run(cron: &str, after: &DateTime<Utc>) -> Result<DateTime<Utc>, String> {
+    let cron = Cron::new(cron)
+        .parse()
+        .map_err(|e| format!("cron 式が正しくありません：{}", e))?;
+    let next = cron
+        .find_next_occurrence(&after.with_timezone(&jst()), false)
+        .map_err(|e| format!("次の予定時刻を計算できません：{}", e))?;
+
+    Ok(next.with_timezone(&Utc))
+}
+
+async fn command_add(
+    bot: &Bot,
+    guild_id: i64,
+    options: &[CommandDataOption],
+) -> Result<String, String> {
+    let mut channel_id = None;
+    let mut cron = None;
+    let mut quiz_type = None;
+    let mut fname = None;
+    let mut mtg_format = None;
+    let mut rare = false;
+    let mut quiz_mode = QuizMode::Text;
+    let mut time_limit = None;
+    for option in options {
+        match (option.name.as_str(), &option.value) {
+            ("channel", CommandDataOptionValue::Channel(id)) => channel_id = Some(i64::from(*id)),
+            ("cron", CommandDataOptionValue::String(s)) => cron = Some(s.trim().to_owned()),
+            ("game", CommandDataOptionValue::String(s)) => quiz_type = QuizType::from_option(s),
+            ("fname", CommandDataOptionValue::String(s)) => fname = Some(s.clone()),
+            ("format", CommandDataOptionValue::String(s)) => mtg_format = Some(s.clone()),
+            ("rare", CommandDataOptionValue::Boolean(b)) => rare = *b,
+            ("mode", CommandDataOptionValue::String(s)) => {
+                quiz_mode = QuizMode::from_option(Some(s))
+            }
+            ("time_limit", CommandDataOptionValue::Integer(i)) => time_limit = Some(*i),
+            _ => {}
+        }
+    }
+    let (Some(channel_id), Some(cron), Some(quiz_type)) = (channel_id, cron, quiz_type) else {
+        return Err("channel, cron, game は必須です".to_owned());
+    };
+
+    // 登録前に cron 式を検証しておく
+    let next = next_run(&cron, &Utc::now())?;
+
+    let schedule = db::insert_schedule(
+        &bot.database,
+        &NewSchedule {
+            guild_id,
+            channel_id,
+            cron,
+            quiz_type,
+            quiz_mode,
+            fname,
+            mtg_format,
+            rare,
+            time_limit,
+        },
+    )
+    .await
+    .map_err(|e| e.to_string())?;
+
+    info!("Added schedule {} for guild {}", schedule.id, guild_id);
+    Ok(format!(
+        "スケジュール #{} を追加しました（次回：{}）",
+        schedule.id,
+        next.with_timezone(&jst()).format("%Y/%m/%d %H:%M")
+    ))
+}
+
+async fn command_list(bot: &Bot, guild_id: i64) -> Result<String, String> {
+    let schedules = db::get_schedules(&bot.database, &guild_id)
+        .await
+        .map_err(|e| e.to_string())?;
+    if schedules.is_empty() {
+        return Ok("スケジュールはありません (`/quizadmin schedule add` で追加)".to_owned());
+    }
+
+    let now = Utc::now();
+    let mut content = "スケジュール一覧\n".to_owned();
+    for schedule in schedules {
+        let next = next_run(&schedule.cron, &now)
+            .map(|next| {
+                next.with_timezone(&jst())
+                    .format("%Y/%m/%d %H:%M")
+                    .to_string()
+            })
+            .unwrap_or_else(|_| "不明".to_owned());
+        content += &format!(
+            "- #{} : <#{}> `{}` {}（次回：{}）\n",
+            schedule.id,
+            schedule.channel_id,
+            schedule.cron,
+            schedule.quiz_type.name(),
+            next
+        );
+    }
+
+    Ok(content)
+}
+
+async fn command_remove(
+    bot: &Bot,
+    guild_id: i64,
+    options: &[CommandDataOption],
+) -> Result<String, String> {
+    let Some(schedule_id) = options.iter().find_map(|option| match option.value {
+        CommandDataOptionValue::Integer(id) if option.name == "id" => Some(id),
+        _ => None,
+    }) else {
+        return Err("id は必須です".to_owned());
+    };
+
+    if db::delete_schedule(&bot.database, &guild_id, &schedule_id)
+        .await
+        .map_err(|e| e.to_string())?
+    {
+        info!("Removed schedule {} for guild {}", schedule_id, guild_id);
+        Ok(format!("スケジュール #{} を削除しました", schedule_id))
+    } else {
+        Ok(format!("スケジュール #{} は見つかりません", schedule_id))
+    }
+}
+
+/// 予定時刻になったスケジュールの早押しクイズを投稿し続ける
+pub(crate) fn start(pool: PgPool, client: HttpClient, ctx: Context) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(TICK);
+        loop {
+            interval.tick().await;
+            if let Err(err) = run_due(&pool, &client, &ctx).await {
+                error!("{}", err);
+            }
+        }
+    });
+}
+
+async fn run_due(pool: &PgPool, client: &HttpClient, ctx: &Context) -> Result<(), String> {
+    let schedules = db::get_all_schedules(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let now = Utc::now();
+    for schedule in schedules {
+        let next = match next_run(&schedule.cron, &schedule.last_run_at) {
+            Ok(next) => next,
+            Err(err) => {
+                error!("schedule {}: {}", schedule.id, err);
+                continue;
+            }
+        };
+        if next > now {
+            continue;
+        }
+
+        // 投稿に失敗しても同じ予定を何度も試さないよう、先に実行済みにする
+        db::update_last_run(pool, &schedule.id, &now)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        if now - next > MAX_DELAY {
+            info!("Skipped schedule {} planned at {}", schedule.id, next);
+            continue;
+        }
+
+        if let Err(err) = post(pool, client, ctx, &schedule).await {
+            error!("schedule {}: {}", schedule.id, err);
+        }
+    }
+
+    Ok(())
+}
+
+async fn post(
+    pool: &PgPool,
+    client: &HttpClient,
+    ctx: &Context,
+    schedule: &Schedule,
+) -> Result<(), String> {
+    let settings = get_settings(pool, Some(schedule.guild_id))
+        .await
+        .map_err(|e| e.to_string())?;
+    let params = QuizParams {
+        user_id: None,
+        channel_id: schedule.channel_id,
+        guild_id: Some(schedule.guild_id),
+        quiz_mode: schedule.quiz_mode,
+        time_limit: schedule.time_limit.or(settings.time_limit),
+        daily_date: None,
+        mask_card_name: settings.mask_card_name,
+        private: false,
+        concurrency: Concurrency::Single,
+        text_as_image: false,
+    };
+    let prefix = "【早押し】最初に正解した人の勝ちです！カード名をそのまま書き込んでも回答できます";
+
+    let (quiz, reply) = match schedule.quiz_type {
+        QuizType::Ygo => {
+            let card = ygo::fetch_card(pool, client, schedule.fname.as_deref(), schedule.quiz_mode)
+                .await?;
+            let filters = schedule
+                .fname
+                .as_ref()
+                .map(|fname| ("カード名", format!("「{}」を含む", fname)))
+                .into_iter()
+                .collect::<Vec<_>>();
+            ygo::start_quiz(pool, client, ctx, &params, &card, prefix, &filters).await?
+        }
+        QuizType::Mtg => {
+            let format = schedule.mtg_format.as_ref().unwrap_or(&settings.mtg_format);
+            let card =
+                mtg::fetch_card(pool, client, format, schedule.rare, schedule.quiz_mode).await?;
+            let mut filters = vec![("フォーマット", mtg::format_name(format).to_owned())];
+            if schedule.rare {
+                filters.push(("レアリティ", "レア以上".to_owned()));
+            }
+            mtg::start_quiz(pool, client, ctx, &params, &card, prefix, &filters).await?
+        }
+    };
+
+    info!("Posted scheduled quiz {}", schedule.id);
+    let message = reply
+        .send_message(&ctx.http, ChannelId::new(schedule.channel_id as u64))
+        .await
+        .map_err(|e| e.to_string())?;
+    set_prompt(pool, &quiz.id, &message.id.into(), None)
+        .await
+        .map_err(|e| e.to_string())
+}
@@ -0,0 +1,110 @@
+use chrono::{DateTime, Utc};
+use sqlx::{FromRow, PgPool};
+
+use crate::db::{QuizMode, QuizType};
+
+#[allow(dead_code)]
+#[derive(FromRow)]
+pub struct Schedule {
+    pub id: i64,
+    pub guild_id: i64,
+    pub channel_id: i64,
+    pub cron: String,
+    pub quiz_type: QuizType,
+    pub quiz_mode: QuizMode,
+    pub fname: Option<String>,
+    pub mtg_format: Option<String>,
+    pub rare: bool,
+    pub time_limit: Option<i64>,
+    pub last_run_at: DateTime<Utc>,
+}
+
+/// 新しく登録するスケジュール
+pub struct NewSchedule {
+    pub guild_id: i64,
+    pub channel_id: i64,
+    pub cron: String,
+    pub quiz_type: QuizType,
+    pub quiz_mode: QuizMode,
+    pub fname: Option<String>,
+    pub mtg_format: Option<String>,
+    pub rare: bool,
+    pub time_limit: Option<i64>,
+}
+
+pub(crate) async fn insert_schedule(
+    pool: &PgPool,
+    schedule: &NewSchedule,
+) -> Result<Schedule, sqlx::Error> {
+    let data: Schedule = sqlx::query_as(
+        r#"
+      INSERT INTO quiz_schedule
+        (guild_id, channel_id, cron, quiz_type, quiz_mode, fname, mtg_format, rare, time_limit)
+      VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+      RETURNING *
+    "#,
+    )
+    .bind(schedule.guild_id)
+    .bind(schedule.channel_id)
+    .bind(&schedule.cron)
+    .bind(schedule.quiz_type)
+    .bind(schedule.quiz_mode)
+    .bind(&schedule.fname)
+    .bind(&schedule.mtg_format)
+    .bind(schedule.rare)
+    .bind(schedule.time_limit)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(data)
+}
+
+pub(crate) async fn get_all_schedules(pool: &PgPool) -> Result<Vec<Schedule>, sqlx::Error> {
+    let data: Vec<Schedule> = sqlx::query_as(r#"SELECT * FROM quiz_schedule"#)
+        .fetch_all(pool)
+        .await?;
+
+    Ok(data)
+}
+
+pub(crate) async fn get_schedules(
+    pool: &PgPool,
+    guild_id: &i64,
+) -> Result<Vec<Schedule>, sqlx::Error> {
+    let data: Vec<Schedule> =
+        sqlx::query_as(r#"SELECT * FROM quiz_schedule WHERE guild_id = $1 ORDER BY id"#)
+            .bind(guild_id)
+            .fetch_all(pool)
+            .await?;
+
+    Ok(data)
+}
+
+/// 削除できたかどうかを返す
+pub(crate) async fn delete_schedule(
+    pool: &PgPool,
+    guild_id: &i64,
+    schedule_id: &i64,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(r#"DELETE FROM quiz_schedule WHERE guild_id = $1 AND id = $2"#)
+        .bind(guild_id)
+        .bind(schedule_id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub(crate) async fn update_last_run(
+    pool: &PgPool,
+    schedule_id: &i64,
+    last_run_at: &DateTime<Utc>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(r#"UPDATE quiz_schedule SET last_run_at = $2 WHERE id = $1"#)
+        .bind(schedule_id)
+        .bind(last_run_at)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,139 @@
+pub mod db;
+
+use serenity::{
+    all::{CommandDataOption, CommandDataOptionValue},
+    builder::CreateCommandOption,
+};
+use tracing::info;
+
+use crate::settings::db::GuildSettings;
+use crate::{mtg, Bot};
+
+pub(crate) fn create_subcommand(c: CreateCommandOption) -> CreateCommandOption {
+    c.description("Settings of quiz bot for this server")
+        .add_sub_option(CreateCommandOption::new(
+            serenity::all::CommandOptionType::SubCommand,
+            "get",
+            "Show current settings",
+        ))
+        .add_sub_option(
+            CreateCommandOption::new(
+                serenity::all::CommandOptionType::SubCommand,
+                "set",
+                "Change settings. Omitted ones are left unchanged",
+            )
+            .add_sub_option(mtg::create_format_option(
+                "Default Magic format when omitted",
+            ))
+            .add_sub_option(CreateCommandOption::new(
+                serenity::all::CommandOptionType::Boolean,
+                "mask",
+                "Hide the card name in the card text",
+            ))
+            .add_sub_option(
+                CreateCommandOption::new(
+                    serenity::all::CommandOptionType::Integer,
+                    "time_limit",
+                    "Default time limit in seconds (0 for no limit)",
+                )
+                .min_int_value(0)
+                .max_int_value(3600),
+            )
+            .add_sub_option(CreateCommandOption::new(
+                serenity::all::CommandOptionType::Boolean,
+                "ygo",
+                "Allow Yu-gi-oh! quizzes",
+            ))
+            .add_sub_option(CreateCommandOption::new(
+                serenity::all::CommandOptionType::Boolean,
+                "mtg",
+                "Allow Magic quizzes",
+            ))
+            .add_sub_option(CreateCommandOption::new(
+                serenity::all::CommandOptionType::Boolean,
+                "alternate_name",
+                "Accept the reading (Yu-gi-oh!) or English name (Magic) as answers",
+            )),
+        )
+}
+
+pub(crate) async fn receive_command(
+    bot: &Bot,
+    guild_id: i64,
+    subc: &CommandDataOption,
+) -> Result<String, String> {
+    let CommandDataOptionValue::SubCommand(options) = &subc.value else {
+        //unreachable
+        panic!()
+    };
+
+    info!(subc.name);
+    match subc.name.as_str() {
+        "get" => command_get(bot, guild_id).await,
+        "set" => command_set(bot, guild_id, options).await,
+        _ => Err(format!("Unknown Command: {}", subc.name)),
+    }
+}
+
+fn describe(settings: &GuildSettings) -> String {
+    let on_off = |b: bool| if b { "有効" } else { "無効" };
+
+    format!(
+        "- Magic の既定フォーマット (format) : {}\n\
+         - カード名を伏せる (mask) : {}\n\
+         - 既定の制限時間 (time_limit) : {}\n\
+         - 遊戯王のクイズ (ygo) : {}\n\
+         - Magic のクイズ (mtg) : {}\n\
+         - 読み・英語名での回答 (alternate_name) : {}\n",
+        settings.mtg_format,
+        on_off(settings.mask_card_name),
+        settings
+            .time_limit
+            .map(|secs| format!("{} 秒", secs))
+            .unwrap_or("なし".to_owned()),
+        on_off(settings.ygo_enabled),
+        on_off(settings.mtg_enabled),
+        on_off(settings.accept_alternate_name),
+    )
+}
+
+async fn command_get(bot: &Bot, guild_id: i64) -> Result<String, String> {
+    let settings = db::get_settings(&bot.database, Some(guild_id))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(format!("現在の設定\n{}", describe(&settings)))
+}
+
+async fn command_set(
+    bot: &Bot,
+    guild_id: i64,
+    options: &[CommandDataOption],
+) -> Result<String, String> {
+    let mut settings = db::get_settings(&bot.database, Some(guild_id))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    for option in options {
+        match (option.name.as_str(), &option.value) {
+            ("format", CommandDataOptionValue::String(s)) => settings.mtg_format = s.clone(),
+            ("mask", CommandDataOptionValue::Boolean(b)) => settings.mask_card_name = *b,
+            ("time_limit", CommandDataOptionValue::Integer(i)) => {
+                settings.time_limit = Some(*i).filter(|secs| *secs > 0)
+            }
+            ("ygo", CommandDataOptionValue::Boolean(b)) => settings.ygo_enabled = *b,
+            ("mtg", CommandDataOptionValue::Boolean(b)) => settings.mtg_enabled = *b,
+            ("alternate_name", CommandDataOptionValue::Boolean(b)) => {
+                settings.accept_alternate_name = *b
+            }
+            _ => {}
+        }
+    }
+
+    db::save_settings(&bot.database, &settings)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    info!("Updated settings for guild {}", guild_id);
+    Ok(format!("設定を変更しました\n{}", describe(&settings)))
+}
@@ -0,0 +1,88 @@
+use sqlx::{FromRow, PgPool};
+
+use crate::db::QuizType;
+
+/// サーバーごとの設定。未設定のサーバーや DM では既定値を使う
+#[derive(FromRow)]
+pub struct GuildSettings {
+    pub guild_id: i64,
+    /// `/quiz mtg new` でフォーマットを省略したときのフォーマット
+    pub mtg_format: String,
+    /// 問題文中のカード名を伏せるか
+    pub mask_card_name: bool,
+    /// 制限時間を省略したときの制限時間（秒）
+    pub time_limit: Option<i64>,
+    pub ygo_enabled: bool,
+    pub mtg_enabled: bool,
+    /// 読み（遊戯王）や英語名（Magic）での回答も正解にするか
+    pub accept_alternate_name: bool,
+}
+
+impl GuildSettings {
+    fn default_for(guild_id: i64) -> GuildSettings {
+        GuildSettings {
+            guild_id,
+            mtg_format: "eternal".to_owned(),
+            mask_card_name: true,
+            time_limit: None,
+            ygo_enabled: true,
+            mtg_enabled: true,
+            accept_alternate_name: true,
+        }
+    }
+
+    pub fn is_enabled(&self, quiz_type: QuizType) -> bool {
+        match quiz_type {
+            QuizType::Ygo => self.ygo_enabled,
+            QuizType::Mtg => self.mtg_enabled,
+        }
+    }
+}
+
+pub(crate) async fn get_settings(
+    pool: &PgPool,
+    guild_id: Option<i64>,
+) -> Result<GuildSettings, sqlx::Error> {
+    let Some(guild_id) = guild_id else {
+        return Ok(GuildSettings::default_for(0));
+    };
+
+    let data: Option<GuildSettings> =
+        sqlx::query_as(r#"SELECT * FROM guild_settings WHERE guild_id = $1"#)
+            .bind(guild_id)
+            .fetch_optional(pool)
+            .await?;
+
+    Ok(data.unwrap_or_else(|| GuildSettings::default_for(guild_id)))
+}
+
+pub(crate) async fn save_settings(
+    pool: &PgPool,
+    settings: &GuildSettings,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+      INSERT INTO guild_settings
+        (guild_id, mtg_format, mask_card_name, time_limit, ygo_enabled, mtg_enabled, accept_alternate_name)
+      VALUES ($1, $2, $3, $4, $5, $6, $7)
+      ON CONFLICT (guild_id) DO UPDATE SET
+        mtg_format = EXCLUDED.mtg_format,
+        mask_card_name = EXCLUDED.mask_card_name,
+        time_limit = EXCLUDED.time_limit,
+        ygo_enabled = EXCLUDED.ygo_enabled,
+        mtg_enabled = EXCLUDED.mtg_enabled,
+        accept_alternate_name = EXCLUDED.accept_alternate_name
+    "#,
+    )
+    .bind(settings.guild_id)
+    .bind(&settings.mtg_format)
+    .bind(settings.mask_card_name)
+    .bind(settings.time_limit)
+    .bind(settings.ygo_enabled)
+    .bind(settings.mtg_enabled)
+    .bind(settings.accept_alternate_name)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,39 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use serenity::all::Http;
+use sqlx::PgPool;
+use tracing::{error, info};
+
+use crate::buttons::retire;
+use crate::db::{finish_quiz, get_abandoned_quizzes, QuizOutcome};
+
+/// 片付けを行う間隔
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// 放置されたクイズを定期的に片付ける。初回は起動直後に行う
+pub(crate) fn start(pool: PgPool, http: Arc<Http>, max_age: Duration) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+        loop {
+            interval.tick().await;
+
+            if let Err(err) = sweep(&pool, &http, &max_age).await {
+                error!("{}", err);
+            }
+        }
+    });
+}
+
+async fn sweep(pool: &PgPool, http: &Http, max_age: &Duration) -> Result<(), sqlx::Error> {
+    let quizzes = get_abandoned_quizzes(pool, max_age).await?;
+
+    for quiz in &quizzes {
+        if let Some(quiz) = finish_quiz(pool, &quiz.id, QuizOutcome::Expired, None).await? {
+            retire(http, &quiz).await;
+        }
+    }
+    info!("Expired {} abandoned quizzes", quizzes.len());
+
+    Ok(())
+}
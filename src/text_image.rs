@@ -0,0 +1,299 @@
+use std::io::Cursor;
+use std::sync::OnceLock;
+
+use ab_glyph::{point, Font, FontArc, PxScale, ScaleFont};
+use image::{ImageFormat, Rgba, RgbaImage};
+use serenity::{all::CommandDataOption, all::CommandDataOptionValue, builder::CreateCommandOption};
+
+use crate::db::QuizType;
+
+/// 同梱する日本語フォントの既定の場所。`QUIZ_FONT_PATH` で変えられる
+pub(crate) const DEFAULT_FONT_PATH: &str = "assets/fonts/NotoSansJP-Regular.ttf";
+
+/// 出題文に添付する画像のファイル名
+pub(crate) const FILE_NAME: &str = "text.png";
+
+pub(crate) const UNAVAILABLE: &str =
+    "フォントが読み込まれていないため、カードテキストを画像にできません";
+
+const WIDTH: u32 = 800;
+const PADDING: u32 = 40;
+const BORDER: u32 = 8;
+const FONT_SIZE: f32 = 30.0;
+const LINE_HEIGHT: f32 = 1.6;
+
+/// 行頭に置かない文字。行末にはみ出させる
+const NO_LINE_START: &str = "、。，．・：；？！ー）」』】〕〉》’”";
+
+static FONT: OnceLock<FontArc> = OnceLock::new();
+
+/// 起動時にフォントを読み込む。読み込めなくても、画像での出題ができなくなるだけ
+pub(crate) fn load_font(path: &str) -> Result<(), String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+    let font = FontArc::try_from_vec(bytes).map_err(|e| format!("{}: {}", path, e))?;
+    FONT.set(font)
+        .map_err(|_| "フォントは既に読み込まれています".to_owned())
+}
+
+pub(crate) fn is_available() -> bool {
+    FONT.get().is_some()
+}
+
+/// 各ゲームの `new` に共通する、カードテキストを画像で送るかの指定
+pub(crate) fn create_image_option() -> CreateCommandOption {
+    CreateCommandOption::new(
+        serenity::all::CommandOptionType::Boolean,
+        "image",
+        "If true, the card text is sent as an image so it can't be pasted into a search engine",
+    )
+}
+
+pub(crate) fn wants_image(options: &[CommandDataOption]) -> bool {
+    options.iter().any(|option| {
+        option.name == "image" && matches!(option.value, CommandDataOptionValue::Boolean(true))
+    })
+}
+
+/// ゲームごとの背景の色
+struct Theme {
+    background: Rgba<u8>,
+    border: Rgba<u8>,
+    text: Rgba<u8>,
+}
+
+impl Theme {
+    fn of(quiz_type: QuizType) -> Theme {
+        match quiz_type {
+            // 効果モンスターのテキスト欄のような色
+            QuizType::Ygo => Theme {
+                background: Rgba([236, 222, 190, 255]),
+                border: Rgba([158, 96, 46, 255]),
+                text: Rgba([24, 18, 12, 255]),
+            },
+            // 黒枠のカードのような色
+            QuizType::Mtg => Theme {
+                background: Rgba([242, 236, 222, 255]),
+                border: Rgba([28, 28, 28, 255]),
+                text: Rgba([20, 20, 20, 255]),
+            },
+        }
+    }
+}
+
+/// カードテキストを折り返して描いた PNG を返す
+pub(crate) fn render(text: &str, quiz_type: QuizType) -> Result<Vec<u8>, String> {
+    render_with(FONT.get().ok_or(UNAVAILABLE)?, text, quiz_type)
+}
+
+fn render_with(font: &FontArc, text: &str, quiz_type: QuizType) -> Result<Vec<u8>, String> {
+    let scaled = font.as_scaled(PxScale::from(FONT_SIZE));
+    let theme = Theme::of(quiz_type);
+
+    let lines = wrap(text, (WIDTH - PADDING * 2) as f32, |c| {
+        scaled.h_advance(scaled.glyph_id(c))
+    });
+    let line_height = FONT_SIZE * LINE_HEIGHT;
+    let height = PADDING * 2 + (line_height * lines.len() as f32).ceil() as u32;
+
+    let mut img = RgbaImage::from_fn(WIDTH, height, |x, y| {
+        if x < BORDER || y < BORDER || x >= WIDTH - BORDER || y >= height - BORDER {
+            theme.border
+        } else {
+            theme.background
+        }
+    });
+
+    // 字面を行の高さの中央に置く
+    let top_margin = (line_height - (scaled.ascent() - scaled.descent())) / 2.0;
+    for (i, line) in lines.iter().enumerate() {
+        let baseline = PADDING as f32 + line_height * i as f32 + top_margin + scaled.ascent();
+        let mut x = PADDING as f32;
+        let mut previous = None;
+        for c in line.chars() {
+            let id = scaled.glyph_id(c);
+            if let Some(previous) = previous {
+                x += scaled.kern(previous, id);
+            }
+            previous = Some(id);
+
+            let glyph = id.with_scale_and_position(FONT_SIZE, point(x, baseline));
+            x += scaled.h_advance(id);
+            let Some(outlined) = font.outline_glyph(glyph) else {
+                continue;
+            };
+            let bounds = outlined.px_bounds();
+            outlined.draw(|gx, gy, coverage| {
+                let (px, py) = (
+                    bounds.min.x as i64 + gx as i64,
+                    bounds.min.y as i64 + gy as i64,
+                );
+                if px < 0 || py < 0 || px >= WIDTH as i64 || py >= height as i64 {
+                    return;
+                }
+                let pixel = img.get_pixel_mut(px as u32, py as u32);
+                *pixel = blend(*pixel, theme.text, coverage);
+            });
+        }
+    }
+
+    let mut png = Cursor::new(Vec::new());
+    img.write_to(&mut png, ImageFormat::Png)
+        .map_err(|e| e.to_string())?;
+
+    Ok(png.into_inner())
+}
+
+fn blend(background: Rgba<u8>, foreground: Rgba<u8>, coverage: f32) -> Rgba<u8> {
+    let coverage = coverage.clamp(0.0, 1.0);
+    Rgba(std::array::from_fn(|i| {
+        (background.0[i] as f32 * (1.0 - coverage) + foreground.0[i] as f32 * coverage).round()
+            as u8
+    }))
+}
+
+/// `max_width` に収まるよう一文字単位で折り返す。改行はそのまま残す
+/// 英単語や数字の途中では折り返さず、句読点や閉じ括弧は行頭に置かずに前の行の末尾にはみ出させる
+fn wrap(text: &str, max_width: f32, advance: impl Fn(char) -> f32) -> Vec<String> {
+    let mut lines = vec![];
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        let mut width = 0.0;
+        for c in paragraph.chars() {
+            let w = advance(c);
+            if width + w > max_width && !line.is_empty() && !NO_LINE_START.contains(c) {
+                // 折り返す位置で単語が切れるなら、単語ごと次の行に送る
+                // 単語が行頭から始まっている場合は、途中で折り返すしかない
+                let word_start = if c.is_ascii_alphanumeric() {
+                    line.char_indices()
+                        .rev()
+                        .find(|(_, c)| !c.is_ascii_alphanumeric())
+                        .map_or(0, |(i, c)| i + c.len_utf8())
+                } else {
+                    line.len()
+                };
+                let word = if word_start == 0 {
+                    String::new()
+                } else {
+                    line.split_off(word_start)
+                };
+                lines.push(std::mem::replace(&mut line, word));
+                width = line.chars().map(&advance).sum();
+            }
+            line.push(c);
+            width += w;
+        }
+        lines.push(line);
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 全ての文字を幅 1 とみなす
+    fn wrap_by_count(text: &str, max: usize) -> Vec<String> {
+        wrap(text, max as f32, |_| 1.0)
+    }
+
+    #[test]
+    fn wrap_long_lines() {
+        assert_eq!(
+            wrap_by_count("このカードは戦闘では破壊されない", 6),
+            vec!["このカードは", "戦闘では破壊", "されない"]
+        );
+    }
+
+    #[test]
+    fn keep_line_breaks() {
+        assert_eq!(
+            wrap_by_count("①：効果\n\n②：効果", 10),
+            vec!["①：効果", "", "②：効果"]
+        );
+    }
+
+    #[test]
+    fn hang_punctuation() {
+        assert_eq!(
+            wrap_by_count("相手に与える。その後、", 6),
+            vec!["相手に与える。", "その後、"]
+        );
+        assert_eq!(
+            wrap_by_count("「青眼の白龍」を", 6),
+            vec!["「青眼の白龍」", "を"]
+        );
+    }
+
+    #[test]
+    fn keep_words_together() {
+        assert_eq!(wrap_by_count("draw 1 card", 7), vec!["draw 1 ", "card"]);
+        assert_eq!(
+            wrap_by_count("攻撃力2500以上", 6),
+            vec!["攻撃力", "2500以上"]
+        );
+        // 1 行に収まらない単語は途中で折り返す
+        assert_eq!(wrap_by_count("Spellcaster", 6), vec!["Spellc", "aster"]);
+    }
+
+    /// 本番と同じく、同梱の日本語フォントで描画を確かめる
+    fn bundled_font() -> FontArc {
+        let bytes = std::fs::read(DEFAULT_FONT_PATH)
+            .unwrap_or_else(|e| panic!("{} を読み込めません: {}", DEFAULT_FONT_PATH, e));
+        FontArc::try_from_vec(bytes).unwrap()
+    }
+
+    #[test]
+    fn bundled_font_has_japanese_glyphs() {
+        let font = bundled_font();
+        for c in "①：このカードを破壊する。「青眼の白龍」攻撃力".chars() {
+            assert_ne!(font.glyph_id(c).0, 0, "no glyph for {}", c);
+        }
+    }
+
+    #[test]
+    fn render_text() {
+        let png = render_with(
+            &bundled_font(),
+            "Draw 2 cards.\n①：このカードを破壊する。",
+            QuizType::Ygo,
+        )
+        .unwrap();
+        let img = image::load_from_memory(&png).unwrap().to_rgba8();
+
+        // 2 行分の高さになる
+        let line_height = (FONT_SIZE * LINE_HEIGHT).ceil() as u32;
+        assert_eq!(
+            img.dimensions(),
+            (
+                WIDTH,
+                PADDING * 2 + (FONT_SIZE * LINE_HEIGHT * 2.0).ceil() as u32
+            )
+        );
+
+        // 枠の内側のどちらの行にも、背景色でない（文字の）画素がある
+        let theme = Theme::of(QuizType::Ygo);
+        for line in 0..2 {
+            let top = PADDING + line_height * line;
+            let inked = (PADDING..WIDTH - PADDING)
+                .flat_map(|x| (top..top + line_height).map(move |y| (x, y)))
+                .filter(|&(x, y)| *img.get_pixel(x, y) != theme.background)
+                .count();
+            assert!(
+                inked > 100,
+                "only {} pixels were drawn on line {}",
+                inked,
+                line
+            );
+        }
+    }
+
+    #[test]
+    fn report_missing_font() {
+        // テスト環境ではフォントを読み込んでいない
+        assert_eq!(
+            render("テキスト", QuizType::Ygo),
+            Err(UNAVAILABLE.to_owned())
+        );
+    }
+}
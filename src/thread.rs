@@ -0,0 +1,91 @@
+use serenity::{
+    all::{
+        AutoArchiveDuration, ChannelId, CommandDataOption, CommandDataOptionValue, CreateThread,
+        EditThread, Http, Message,
+    },
+    builder::CreateCommandOption,
+};
+use tracing::{error, info};
+
+use crate::db::{get_quiz_by_id, set_thread, Quiz, QuizType};
+use crate::Bot;
+
+/// 本人にだけ表示するメッセージからはスレッドを作れない
+pub(crate) const PRIVATE_THREAD_ERROR: &str =
+    "本人にだけ表示するクイズはスレッドで出題できません (`private:False` を指定してください)";
+
+/// 各ゲームの `new` に共通する、スレッドで出題するかの指定
+pub(crate) fn create_thread_option() -> CreateCommandOption {
+    CreateCommandOption::new(
+        serenity::all::CommandOptionType::Boolean,
+        "thread",
+        "If true, the quiz is held in its own thread. Plain messages there count as answers",
+    )
+}
+
+pub(crate) fn wants_thread(subc: &CommandDataOption) -> bool {
+    let CommandDataOptionValue::SubCommand(options) = &subc.value else {
+        return false;
+    };
+    options.iter().any(|option| {
+        option.name == "thread" && matches!(option.value, CommandDataOptionValue::Boolean(true))
+    })
+}
+
+/// 出題メッセージからクイズ用のスレッドを作る
+pub(crate) async fn open(
+    bot: &Bot,
+    http: &Http,
+    quiz: &Quiz,
+    prompt: &Message,
+) -> Result<(), String> {
+    let name = format!(
+        "{}クイズ #{}",
+        match quiz.quiz_type {
+            QuizType::Ygo => "遊戯王",
+            QuizType::Mtg => "Magic ",
+        },
+        quiz.id
+    );
+    let thread = prompt
+        .channel_id
+        .create_thread_from_message(
+            http,
+            prompt.id,
+            CreateThread::new(name).auto_archive_duration(AutoArchiveDuration::OneHour),
+        )
+        .await
+        .map_err(|e| format!("スレッドを作れませんでした：{}", e))?;
+    info!("Opened thread {} for quiz {}", thread.id, quiz.id);
+
+    set_thread(&bot.database, &quiz.id, &thread.id.into())
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// クイズの発表などを送るチャンネル。スレッドがあればそちらに送る
+pub(crate) fn reply_channel(quiz: &Quiz) -> ChannelId {
+    ChannelId::new(quiz.thread_id.unwrap_or(quiz.channel_id) as u64)
+}
+
+/// 操作の結果クイズが終わっていれば、そのスレッドをアーカイブする
+/// 結果の発表をスレッドに送った後で呼ぶこと
+pub(crate) async fn archive_if_finished(bot: &Bot, http: &Http, quiz: &Quiz) {
+    let Some(thread_id) = quiz.thread_id else {
+        return;
+    };
+    match get_quiz_by_id(&bot.database, &quiz.id).await {
+        Err(sqlx::Error::RowNotFound) => archive(http, thread_id).await,
+        Err(err) => error!("{}", err),
+        Ok(_) => {}
+    }
+}
+
+pub(crate) async fn archive(http: &Http, thread_id: i64) {
+    if let Err(err) = ChannelId::new(thread_id as u64)
+        .edit_thread(http, EditThread::new().archived(true))
+        .await
+    {
+        error!("Failed to archive thread {}: {}", thread_id, err);
+    }
+}
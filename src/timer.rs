@@ -0,0 +1,90 @@
+use std::sync::Arc;
+
+use chrono::Utc;
+use serenity::all::{Http, Mentionable, UserId};
+use sqlx::PgPool;
+use tracing::{error, info};
+
+use crate::db::{finish_quiz, get_timed_quizzes, Quiz, QuizOutcome, QuizType};
+use crate::http::HttpClient;
+use crate::thread::{archive, reply_channel};
+use crate::{mtg, ygo};
+
+/// 制限時間付きのクイズの締め切りを待ち、時間切れになったら答えを発表する
+pub(crate) fn schedule(pool: PgPool, http: Arc<Http>, client: HttpClient, quiz: &Quiz) {
+    let Some(deadline) = quiz.deadline else {
+        return;
+    };
+    let quiz_id = quiz.id;
+
+    tokio::spawn(async move {
+        let wait = (deadline - Utc::now()).to_std().unwrap_or_default();
+        tokio::time::sleep(wait).await;
+
+        if let Err(err) = time_up(&pool, &http, &client, &quiz_id).await {
+            error!("{}", err);
+        }
+    });
+}
+
+/// 起動時に、DB に残っている制限時間付きのクイズを全て待ち直す
+pub(crate) async fn reschedule_all(pool: &PgPool, http: &Arc<Http>, client: &HttpClient) {
+    match get_timed_quizzes(pool).await {
+        Ok(quizzes) => {
+            info!("Rescheduled {} timed quizzes", quizzes.len());
+            for quiz in &quizzes {
+                schedule(pool.clone(), http.clone(), client.clone(), quiz);
+            }
+        }
+        Err(err) => error!("{}", err),
+    }
+}
+
+async fn time_up(
+    pool: &PgPool,
+    http: &Http,
+    client: &HttpClient,
+    quiz_id: &i64,
+) -> Result<(), String> {
+    // 答えは削除する前に取っておく
+    let quiz_type = match crate::db::get_quiz_by_id(pool, quiz_id).await {
+        Ok(quiz) => quiz.quiz_type,
+        // 既に回答済み
+        Err(sqlx::Error::RowNotFound) => return Ok(()),
+        Err(err) => return Err(err.to_string()),
+    };
+    let answer = match quiz_type {
+        QuizType::Ygo => ygo::reveal(pool, client, quiz_id).await?,
+        QuizType::Mtg => mtg::reveal(pool, quiz_id).await?,
+    };
+
+    let Some(quiz) = finish_quiz(pool, quiz_id, QuizOutcome::Timeout, None)
+        .await
+        .map_err(|e| e.to_string())?
+    else {
+        // 答えを用意している間に回答された
+        return Ok(());
+    };
+    info!("Timeout: quiz {} for {:?}", quiz.id, quiz.user_id);
+    crate::buttons::disable_buttons(http, &quiz).await;
+
+    let reply = answer.with_content(format!(
+        "{}時間切れ！",
+        quiz.user_id
+            .map(|user_id| format!("{} ", UserId::new(user_id as u64).mention()))
+            .unwrap_or_default()
+    ));
+
+    match (quiz.private, quiz.user_id) {
+        // 本人にだけ表示するクイズの答えは、チャンネルではなく DM で知らせる
+        (true, Some(user_id)) => reply.send_dm(http, UserId::new(user_id as u64)).await,
+        _ => reply.send_message(http, reply_channel(&quiz)).await,
+    }
+    .map_err(|e| e.to_string())?;
+
+    if let Some(thread_id) = quiz.thread_id {
+        archive(http, thread_id).await;
+    }
+
+    Ok(())
+}
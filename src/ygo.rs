@@ -1,20 +1,66 @@
+pub mod api;
 pub mod db;
+pub mod text;
 
+use std::collections::HashMap;
+
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
 use serenity::{
     all::{
-        CommandDataOptionValue, CommandInteraction, CreateAttachment,
-        CreateInteractionResponseFollowup, EditInteractionResponse, Mentionable,
+        Colour, CommandDataOption, CommandDataOptionValue, CommandInteraction, CreateActionRow,
+        CreateAttachment, CreateButton, CreateEmbed, Mentionable, User,
     },
     builder::CreateCommandOption,
     client::Context,
-    futures::TryFutureExt,
 };
+use sqlx::PgPool;
 use tracing::{error, info};
 
+use crate::http::HttpClient;
 use crate::Bot;
 
-use crate::common::roughly_card_name_equal;
+use crate::art::obscure_art;
+use crate::blank::{self, NO_BLANK};
+use crate::buttons::{disable_buttons, prompt_sent, quiz_buttons};
+use crate::channels::check_command_channel;
+use crate::common::{
+    create_mode_option, create_time_limit_option, mask_card_name, partial_segments, prompt_embed,
+    roughly_card_name_equal, score_message, DrawError, MAX_DRAWS, NO_USABLE_CARD, QUIZ_ENDED,
+};
+use crate::daily::ALREADY_ENTERED;
+use crate::db::{
+    finish_quiz, get_quiz_by_id, is_skipped_card, record_skipped_card, QuizMode, QuizOutcome,
+    QuizParams, QuizType,
+};
+use crate::preferences::db::get_preferences;
+use crate::preferences::{create_private_option, wants_private};
+use crate::reply::{Deferred, Reply};
+use crate::settings::db::{get_settings, GuildSettings};
+use crate::text_image::{self, create_image_option};
+use crate::thread::{create_thread_option, wants_thread, PRIVATE_THREAD_ERROR};
 use crate::ygo::db::{get_quiz, new_quiz};
+use crate::ygo::text::{extract_text, CardText};
+
+/// 埋め込みの色
+const EMBED_COLOUR: Colour = Colour::new(0xB8860B);
+
+/// 出題に使う遊戯王カードの情報
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Card {
+    pub konami_id: i64,
+    pub card_name: String,
+    pub card_name_ruby: String,
+    /// 表示用の全文
+    pub card_text: String,
+    /// 効果ごとに分けたテキスト。以前に保存した日替わりのカードにはない
+    #[serde(default)]
+    pub text: CardText,
+    pub image_url: String,
+    /// 先読みしておいたイラスト。日替わりのカードとしては保存しない
+    #[serde(skip)]
+    pub art: Option<Vec<u8>>,
+}
 
 pub(crate) fn create_subcommand(c: CreateCommandOption) -> CreateCommandOption {
     c.description("Communicate with Yu-gi-oh! quiz bot")
@@ -28,109 +74,215 @@ pub(crate) fn create_subcommand(c: CreateCommandOption) -> CreateCommandOption {
                 serenity::all::CommandOptionType::String,
                 "fname",
                 "If specified, only cards with it in the card name will be asked",
-            )),
+            ))
+            .add_sub_option(create_mode_option())
+            .add_sub_option(create_time_limit_option())
+            .add_sub_option(create_private_option(
+                "If true, the quiz is shown only to you. Defaults to your preference",
+            ))
+            .add_sub_option(create_thread_option())
+            .add_sub_option(create_image_option()),
         )
 }
 
 pub(crate) async fn receive_command(bot: &Bot, ctx: &Context, command: CommandInteraction) {
-    command.defer(&ctx.http).await.unwrap();
-
     let CommandDataOptionValue::SubCommandGroup(after_ygo) = &command.data.options[0].value else {
         //unreachable
         panic!()
     };
 
+    let private = wants_private(bot, command.user.id.into(), &after_ygo[0]);
+    let Some(deferred) = Deferred::start(&ctx.http, command.id, &command.token, private).await
+    else {
+        return;
+    };
+    if let Err(refusal) = check_command_channel(&bot.database, &command).await {
+        deferred.respond(Err(refusal)).await;
+        return;
+    }
+
+    let thread = wants_thread(&after_ygo[0]);
+
     let subc = after_ygo[0].name.as_str();
     let result = match subc {
+        "new" if private && thread => Err(PRIVATE_THREAD_ERROR.to_owned()),
         "new" => {
             info!("{:?}", after_ygo[0].value);
-            let CommandDataOptionValue::SubCommand(ref params) = after_ygo[0].value else {
+            let CommandDataOptionValue::SubCommand(ref options) = after_ygo[0].value else {
                 //unreachable
                 panic!()
             };
-            let fname = if !params.is_empty() {
-                params[0].value.as_str()
-            } else {
-                None
-            };
-            info!(fname);
-            command_new(bot, ctx, &command, fname).await
+            command_new(bot, ctx, &command, options, private).await
         }
         _ => Err(format!("Unknown Command: {}", subc)),
     };
 
-    match result {
-        Ok((msg, attachments)) => {
-            match command
-                .edit_response(&ctx.http, EditInteractionResponse::new().content(msg))
-                .and_then(|_msg| {
-                    command.create_followup(
-                        &ctx.http,
-                        CreateInteractionResponseFollowup::new().files(attachments),
-                    )
-                })
-                .await
-            {
-                Ok(_) => {}
-                Err(err) => {
-                    error!("{}", err)
-                }
-            }
-        }
-        Err(msg) => {
-            error!(msg);
-            match command
-                .edit_response(&ctx.http, EditInteractionResponse::new().content(msg))
-                .await
-            {
-                Ok(_) => {}
-                Err(err) => {
-                    error!("{}", err)
-                }
-            }
-        }
+    if let Some((quiz, prompt)) = deferred.respond_with(result).await {
+        prompt_sent(bot, &ctx.http, &command.token, &quiz, &prompt, thread).await;
     }
 }
 
-fn extract_text_from_document(
-    document: &scraper::Html,
-    selector: &scraper::Selector,
-    card_name: &str,
-) -> String {
-    let card_text: String = document
-        .select(selector)
-        .map(|t| t.inner_html())
-        .collect::<Vec<_>>()
-        .join("")
-        .split('\n')
-        .filter_map(|card_line: &str| {
-            if card_line.contains("<div class=\"text_title\">") {
-                None
-            } else if card_line.contains("</div>") {
-                None
-            } else if card_line.trim() == "" {
-                None
-            } else {
-                Some(card_line.trim())
-            }
-        })
-        .collect::<Vec<_>>()
-        .join("\n")
-        .replace("<br>", "\n")
-        .replace(card_name, "<カード名>")
-        .to_string();
-
-    card_text
+async fn fetch_art(client: &HttpClient, image_url: &str) -> Result<Vec<u8>, String> {
+    client
+        .get_bytes(client.get(image_url))
+        .await
+        .map_err(|err| format!("画像取得エラー：{}", err))
+}
+
+/// 誤答・ヒントの回数に応じて少しずつ鮮明になるイラストを添付する
+/// 出題時に保存したイラストを使い、保存していない古いクイズでは取得し直す
+/// 用意できなかった場合はログに残し、イラストなしで返信する
+async fn art_attachment(
+    client: &HttpClient,
+    quiz: &db::Quiz,
+    level: usize,
+) -> Option<CreateAttachment> {
+    let art = match &quiz.art {
+        Some(art) => Ok(art.clone()),
+        None => fetch_art(client, &quiz.image_url).await,
+    };
+    match art.and_then(|art| obscure_art(&art, level)) {
+        Ok(png) => Some(CreateAttachment::bytes(png, "image.png")),
+        Err(err) => {
+            error!("Failed to attach the art of quiz {}: {}", quiz.quiz_id, err);
+            None
+        }
+    }
 }
 
 async fn command_new(
     bot: &Bot,
-    _: &Context,
+    ctx: &Context,
     command: &CommandInteraction,
+    options: &[CommandDataOption],
+    private: bool,
+) -> Result<(crate::db::Quiz, Reply), String> {
+    let mut cmd_arg_map = HashMap::new();
+    for opt in options {
+        cmd_arg_map.insert(opt.name.as_str(), &opt.value);
+    }
+
+    let settings = get_settings(&bot.database, command.guild_id.map(Into::into))
+        .await
+        .map_err(|e| e.to_string())?;
+    if !settings.is_enabled(QuizType::Ygo) {
+        return Err("このサーバーでは遊戯王のクイズは無効になっています".to_owned());
+    }
+    let preferences = get_preferences(&bot.database, &command.user.id.into())
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let op_fname = cmd_arg_map.get("fname").and_then(|res| res.as_str());
+    info!(op_fname);
+    let params = QuizParams {
+        user_id: Some(command.user.id.into()),
+        channel_id: command.channel_id.into(),
+        guild_id: command.guild_id.map(Into::into),
+        quiz_mode: QuizMode::from_option(cmd_arg_map.get("mode").and_then(|res| res.as_str())),
+        time_limit: cmd_arg_map
+            .get("time_limit")
+            .and_then(|res| res.as_i64())
+            .or(settings.time_limit),
+        daily_date: None,
+        mask_card_name: settings.mask_card_name,
+        private,
+        concurrency: preferences.concurrency,
+        text_as_image: text_image::wants_image(options),
+    };
+    if params.text_as_image && !text_image::is_available() {
+        return Err(text_image::UNAVAILABLE.to_owned());
+    }
+
+    // 条件を指定しない出題には、先読みしておいたカードを使う
+    let prefetched = match op_fname {
+        Some(_) => None,
+        None => {
+            let pool = bot.database.clone();
+            let client = bot.http_client.clone();
+            let mode = params.quiz_mode;
+            bot.ygo_cards.take(mode, move || {
+                prefetch_card(pool.clone(), client.clone(), mode)
+            })
+        }
+    };
+    let card = match prefetched {
+        Some(card) => card,
+        None => fetch_card(&bot.database, &bot.http_client, op_fname, params.quiz_mode).await?,
+    };
+
+    let filters = op_fname
+        .map(|fname| ("カード名", format!("「{}」を含む", fname)))
+        .into_iter()
+        .collect::<Vec<_>>();
+    start_quiz(
+        &bot.database,
+        &bot.http_client,
+        ctx,
+        &params,
+        &card,
+        "",
+        &filters,
+    )
+    .await
+}
+
+/// 条件に合うカードをランダムに 1 枚取得する
+/// 出題に使えないカードを引いた場合は、記録してから引き直す
+pub(crate) async fn fetch_card(
+    pool: &PgPool,
+    client: &HttpClient,
     op_fname: Option<&str>,
-) -> Result<(String, Vec<CreateAttachment>), String> {
-    let client = reqwest::Client::new();
+    mode: QuizMode,
+) -> Result<Card, String> {
+    for _ in 0..MAX_DRAWS {
+        let info = draw_card(client, op_fname, mode).await?;
+        let card_id = info.id.to_string();
+        if is_skipped_card(pool, QuizType::Ygo, &card_id)
+            .await
+            .map_err(|e| e.to_string())?
+        {
+            info!("Redraw: `{}` ({}) was skipped before", info.name, card_id);
+            continue;
+        }
+
+        match load_card(client, &info).await {
+            // 他の出題形式では使えるので、記録はしない
+            Ok(card)
+                if mode == QuizMode::Blank
+                    && blank::choose(&card.card_text, QuizType::Ygo).is_none() =>
+            {
+                info!(
+                    "Redraw: `{}` ({}) has no words to blank",
+                    info.name, card_id
+                );
+            }
+            Ok(card) => return Ok(card),
+            Err(DrawError::Unusable(reason)) => {
+                info!("Skipped `{}` ({}): {}", info.name, card_id, reason);
+                record_skipped_card(pool, QuizType::Ygo, &card_id, &reason)
+                    .await
+                    .map_err(|e| e.to_string())?;
+            }
+            Err(DrawError::Failed(err)) => return Err(err),
+        }
+    }
 
+    Err(NO_USABLE_CARD.to_owned())
+}
+
+/// 先読み用に、条件を指定せずにカードを取得する。イラストも合わせて取得しておく
+async fn prefetch_card(pool: PgPool, client: HttpClient, mode: QuizMode) -> Result<Card, String> {
+    let mut card = fetch_card(&pool, &client, None, mode).await?;
+    card.art = Some(fetch_art(&client, &card.image_url).await?);
+
+    Ok(card)
+}
+
+async fn draw_card(
+    client: &HttpClient,
+    op_fname: Option<&str>,
+    mode: QuizMode,
+) -> Result<api::CardInfo, String> {
     let mut query = vec![
         ("num", "1"),
         ("offset", "0"),
@@ -142,31 +294,30 @@ async fn command_new(
     if let Some(fname) = op_fname {
         query.push(("fname", fname));
     }
+    if mode == QuizMode::Flavor {
+        // フレーバーテキストを持つのは通常モンスターだけ
+        query.push(("type", "Normal Monster"));
+    }
 
-    let card: serde_json::Value = serde_json::from_str::<serde_json::Value>(
+    api::parse_cardinfo(
         &client
-            .get("https://db.ygoprodeck.com/api/v7/cardinfo.php")
-            .query(&query)
-            .send()
-            .await
-            .map_err(|e| e.to_string())?
-            .text()
-            .await
-            .map_err(|e| e.to_string())?,
-    )
-    .map_err(|e| e.to_string())?
-    .get("data")
-    .ok_or("API 応答の解析失敗")?
-    .as_array()
-    .ok_or("API 応答の解析失敗")?[0]
-        .clone();
-    let konami_id = card
-        .get("misc_info")
-        .ok_or("API 応答の解析失敗")?
-        .as_array()
-        .ok_or("API 応答の解析失敗")?[0]
-        .get("konami_id")
-        .ok_or("API 応答の解析失敗")?;
+            .get_text(
+                client
+                    .get("https://db.ygoprodeck.com/api/v7/cardinfo.php")
+                    .query(&query),
+            )
+            .await?,
+    )?
+    .into_iter()
+    .next()
+    .ok_or("条件に合うカードが見つかりませんでした".to_owned())
+}
+
+/// 遊戯王カードデータベースから日本語のカード名とテキストを取得する
+async fn load_card(client: &HttpClient, card: &api::CardInfo) -> Result<Card, DrawError> {
+    let konami_id = card.konami_id().ok_or(DrawError::Unusable(
+        "遊戯王カードデータベースに登録されていません".to_owned(),
+    ))?;
     info!("konami_id = {}", konami_id);
     let url = format!(
         "https://www.db.yugioh-card.com/yugiohdb/card_search.action?ope=2&cid={}&request_locale=ja",
@@ -174,170 +325,485 @@ async fn command_new(
     );
 
     info!("konami_db_url = {}", url);
-    let html = reqwest::get(url)
-        .await
-        .map_err(|e| e.to_string())?
-        .text()
+    let html = client
+        .get_page(client.get(&url))
         .await
-        .map_err(|e| e.to_string())?;
+        .map_err(DrawError::Failed)?;
 
+    // 解析した文書は await をまたいで持てないので、別の関数で解析する
     //https://github.com/causal-agent/scraper/issues/75
-    let x = || ->Result<
-      (
-        std::string::String,
-        std::string::String,
-        std::string::String,
-      ),
-      String,
-    > {
-        let document = scraper::Html::parse_document(&html);
-        let name_selector = scraper::Selector::parse("#cardname h1").map_err(|e| e.to_string())?;
-        let card_names = document
-            .select(&name_selector)
-            .next()
-            .ok_or("遊戯王DBの解析失敗")?
-            .text()
-            .collect::<Vec<_>>();
-        let card_name: String = card_names[2].trim().to_string();
-        let card_name_ruby: String = card_names[1].trim().to_string();
-
-        // まずペンデュラムかどうかを判定
-        let type_selector = scraper::Selector::parse("#CardSet > div.top > div:nth-child(3) > div:nth-child(3) > div > p")        
-            .map_err(|e| e.to_string())?;
-        let card_type = document.select(&type_selector).next().ok_or("遊戯王DBの解析失敗")?.text().collect::<Vec<_>>()[0];
-        let is_pendulum = card_type.contains("ペンデュラム");
-
-        let (card_text_pen, card_text) =
-            if is_pendulum {
-                let pen_selector = scraper::Selector::parse("#CardSet > div.top > div:nth-child(4) > div:nth-child(4) > div")
-                    .map_err(|e| e.to_string())?;
-                let text_selector = scraper::Selector::parse("#CardSet > div.top > div:nth-child(5) > div")
-                    .map_err(|e| e.to_string())?;
-                (extract_text_from_document(&document, &pen_selector, &card_name), 
-                    extract_text_from_document(&document, &text_selector, &card_name))
-            } else {
-                let text_selector = scraper::Selector::parse("#CardSet > div.top > div:nth-child(4) > div")
-                    .map_err(|e| e.to_string())?;
-                ("".to_owned(), extract_text_from_document(&document, &text_selector, &card_name))
-            };
-
-        let concatted = format!("{}{}", 
-            if card_text_pen.is_empty() {
-                "".to_owned()
-            }else {
-                format!("ペンデュラム効果:\n{}\n", card_text_pen)
-            }, 
-            card_text
-        );
+    let (card_name, card_name_ruby, text) = parse_card_page(&html, &card.frame_type)?;
+    if text.is_empty() {
+        return Err(DrawError::Unusable("カードテキストがありません".to_owned()));
+    }
 
-        Ok((card_name, card_name_ruby, concatted))
+    let card = Card {
+        konami_id,
+        card_name,
+        card_name_ruby,
+        card_text: text.to_string(),
+        text,
+        image_url: card.cropped_image_url().unwrap_or("").to_owned(),
+        art: None,
     };
 
-    let (card_name, card_name_ruby, card_text) = x()?;
+    Ok(card)
+}
+
+/// クイズを登録し、最初のイラストを添えた出題文を返す
+pub(crate) async fn start_quiz(
+    database: &PgPool,
+    client: &HttpClient,
+    ctx: &Context,
+    params: &QuizParams,
+    card: &Card,
+    prefix: &str,
+    filters: &[(&str, String)],
+) -> Result<(crate::db::Quiz, Reply), String> {
+    let art = match &card.art {
+        Some(art) => art.clone(),
+        None => fetch_art(client, &card.image_url).await?,
+    };
+    let attachment = CreateAttachment::bytes(obscure_art(&art, 0)?, "image.png");
 
-    let response = reqwest::get(
-        card.get("card_images")
-            .and_then(|c| c.as_array())
-            .and_then(|c| c.first())
-            .and_then(|c| c.get("image_url_cropped"))
-            .and_then(|c| c.as_str())
-            .unwrap_or(""),
+    let segments = (params.quiz_mode == QuizMode::Partial)
+        .then(|| segments(Some(&card.text), &card.card_text));
+    let blank = match params.quiz_mode {
+        QuizMode::Blank => Some(blank::choose(&card.card_text, QuizType::Ygo).ok_or(NO_BLANK)?),
+        _ => None,
+    };
+    let (quiz, replaced) = new_quiz(
+        database,
+        params,
+        card,
+        &art,
+        segments.as_ref().map(|segments| segments.len() as i32),
+        blank.as_ref().map(|blank| blank.answer.as_str()),
     )
     .await
-    .map_err(|_err| "画像取得エラー")?;
-    let img_bytes = response.bytes().await.unwrap();
+    .map_err(|e| format!("データベースでエラーが発生しました：{}", e))?
+    .ok_or(ALREADY_ENTERED)?;
+    info!(
+        "Start quiz about `{}` for `{:?}`",
+        card.card_name, params.user_id
+    );
+    crate::timer::schedule(database.clone(), ctx.http.clone(), client.clone(), &quiz);
+    for replaced in &replaced {
+        crate::buttons::retire(&ctx.http, replaced).await;
+    }
 
-    let content = match new_quiz(
-        &bot.database,
-        &command.user.id.into(),
-        &konami_id.as_i64().unwrap(),
-        &card_name,
-        &card_name_ruby,
-        &card_text,
+    let text = match (params.quiz_mode, &segments) {
+        // 効果のない通常モンスターなどは全文を出す
+        (QuizMode::Effect, _) => card
+            .text
+            .effects
+            .choose(&mut rand::thread_rng())
+            .unwrap_or(&card.card_text),
+        (QuizMode::Partial, Some(segments)) => segments.first().unwrap_or(&card.card_text),
+        _ => match &blank {
+            Some(blank) => &blank.text,
+            None => &card.card_text,
+        },
+    };
+    // 穴埋めではカード名を見せる
+    let text = if params.mask_card_name && blank.is_none() {
+        mask_card_name(text, &card.card_name)
+    } else {
+        text.clone()
+    };
+    let filters = filters
+        .iter()
+        .cloned()
+        .chain(
+            segments
+                .iter()
+                .map(|segments| ("表示中のテキスト", format!("1 / {}", segments.len()))),
+        )
+        .collect::<Vec<_>>();
+    let subject = match &blank {
+        Some(_) => card.card_name.as_str(),
+        None => "遊戯王カード",
+    };
+    let (embed, fallback) = prompt_embed(subject, params, &filters, &text);
+
+    info!(fallback);
+    let reply = Reply {
+        content: prefix.to_owned(),
+        embeds: vec![embed
+            .colour(EMBED_COLOUR)
+            .thumbnail(format!("attachment://{}", attachment.filename))],
+        fallback,
+        attachments: vec![attachment],
+        components: vec![quiz_buttons(&quiz, false)],
+    };
+    let reply = if params.text_as_image {
+        reply.with_text_image(&text, QuizType::Ygo)?
+    } else {
+        reply
+    };
+
+    Ok((quiz, reply))
+}
+
+/// 遊戯王カードデータベースのカードのページから、カード名・読み・テキストを取り出す
+/// `frame_type` は YGOPRODeck の `frameType`
+fn parse_card_page(html: &str, frame_type: &str) -> Result<(String, String, CardText), DrawError> {
+    let document = scraper::Html::parse_document(html);
+    let select = |selector: &str| -> Option<scraper::ElementRef> {
+        let selector = scraper::Selector::parse(selector).ok()?;
+        document.select(&selector).next()
+    };
+
+    // カード名の欄がなければ、メンテナンス中などカードのページではない。引き直しても解決しない
+    let card_names = select("#cardname h1")
+        .ok_or(DrawError::Failed(
+            "遊戯王DBの解析失敗：カードのページではありません".to_owned(),
+        ))?
+        .text()
+        .collect::<Vec<_>>();
+    // 日本語版のないカードは、カードのページでも日本語のカード名やテキストがない
+    let unusable = |what: &str| DrawError::Unusable(format!("日本語の{}がありません", what));
+    if card_names.len() < 3 {
+        return Err(unusable("カード名"));
+    }
+    let card_name: String = card_names[2].trim().to_string();
+    let card_name_ruby: String = card_names[1].trim().to_string();
+
+    let text_of = |selector: &str| {
+        select(selector)
+            .map(extract_text)
+            .ok_or_else(|| unusable("カードテキスト"))
+    };
+    // ペンデュラムモンスターはペンデュラム効果の欄がある分、カードテキストの欄が後ろにずれる
+    let text = if frame_type.ends_with("_pendulum") {
+        CardText::parse(
+            frame_type,
+            &text_of("#CardSet > div.top > div:nth-child(5) > div")?,
+            &text_of("#CardSet > div.top > div:nth-child(4) > div:nth-child(4) > div")?,
+        )
+    } else {
+        CardText::parse(
+            frame_type,
+            &text_of("#CardSet > div.top > div:nth-child(4) > div")?,
+            "",
+        )
+    };
+
+    Ok((card_name, card_name_ruby, text))
+}
+
+/// テキストを少しずつ見せる出題での区切り
+/// 効果ごとに分けたテキストがなければ（以前に保存した日替わりのカードなど）、行ごとに分ける
+fn segments(text: Option<&CardText>, card_text: &str) -> Vec<String> {
+    match text.filter(|text| !text.is_empty()) {
+        Some(text) => text.segments(),
+        None => partial_segments(
+            card_text
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .map(str::to_owned)
+                .collect(),
+        ),
+    }
+}
+
+fn answer_url(konami_id: i64) -> String {
+    format!(
+        "https://www.db.yugioh-card.com/yugiohdb/card_search.action?ope=2&cid={}&request_locale=ja",
+        konami_id
     )
-    .await
-    {
-        Ok(msg) => {
-            info!("{}", msg);
-            format!(
-                "{}次のカードテキストを持つ遊戯王カードは？(`/quiz ans` で回答)\n\n{}",
-                if let Some(fname) = op_fname {
-                    format!("カード名に「{}」が含まれている、", fname)
-                } else {
-                    "".to_owned()
-                },
-                card_text
-            )
-        }
-        Err(err) => {
-            error!("{}", err);
-            format!("データベースでエラーが発生しました：{}", err)
-        }
+}
+
+/// 正解を発表する埋め込みと、モザイクなしのイラスト
+pub(crate) async fn reveal(
+    pool: &sqlx::PgPool,
+    client: &HttpClient,
+    quiz_id: &i64,
+) -> Result<Reply, String> {
+    let quiz = get_quiz(pool, quiz_id).await.map_err(|e| e.to_string())?;
+    let blank_answer = get_quiz_by_id(pool, quiz_id)
+        .await
+        .map_err(|e| e.to_string())?
+        .blank_answer;
+    let url = answer_url(quiz.konami_id);
+
+    let attachment = art_attachment(client, &quiz, usize::MAX).await;
+    let embed = CreateEmbed::new()
+        .title(format!("正解：{}", quiz.card_name))
+        .url(&url)
+        .colour(EMBED_COLOUR)
+        .description(&quiz.card_text);
+    // 読みが空のカードもあり、空の欄は送れない
+    let embed = if quiz.card_name_ruby.is_empty() {
+        embed
+    } else {
+        embed.field("読み", &quiz.card_name_ruby, false)
+    };
+    let embed = match &blank_answer {
+        Some(answer) => embed.field("空欄に入る言葉", answer, false),
+        None => embed,
+    };
+    let embed = match &attachment {
+        Some(attachment) => embed.image(format!("attachment://{}", attachment.filename)),
+        None => embed,
     };
 
-    info!(content);
-    Ok((
-        content,
-        vec![CreateAttachment::bytes(img_bytes, "image.jpg")],
-    ))
+    Ok(Reply {
+        embeds: vec![embed],
+        fallback: match &blank_answer {
+            Some(answer) => format!(
+                "空欄に入る言葉は「{}」でした（「{}」）\n {}",
+                answer, quiz.card_name, url
+            ),
+            None => format!(
+                "正解は「{}」（{}）でした \n {}",
+                quiz.card_name, quiz.card_name_ruby, url
+            ),
+        },
+        attachments: attachment.into_iter().collect(),
+        components: vec![CreateActionRow::Buttons(vec![
+            CreateButton::new_link(url).label("遊戯王カードデータベース")
+        ])],
+        ..Default::default()
+    })
 }
 
-pub async fn command_ans(
+/// 回答が正解か。穴埋めでは空欄に入る言葉と、それ以外ではカード名と比べる
+fn is_correct(
+    quiz: &db::Quiz,
+    common_quiz: &crate::db::Quiz,
+    settings: &GuildSettings,
+    input: &str,
+) -> bool {
+    match &common_quiz.blank_answer {
+        Some(answer) => blank::is_correct(input, answer),
+        None => roughly_card_name_equal(
+            input,
+            &quiz.card_name,
+            settings
+                .accept_alternate_name
+                .then_some(quiz.card_name_ruby.as_str()),
+        ),
+    }
+}
+
+/// 正解かどうかだけを確かめる。誤答を記録せず、ヒントも用意しない
+/// 早押しクイズのチャンネルへの普通の書き込みは、ほとんどが回答ではないので、まずこちらで確かめる
+pub(crate) async fn check_answer(
     bot: &Bot,
-    _: &Context,
-    command: &CommandInteraction,
-) -> Result<String, String> {
-    let CommandDataOptionValue::SubCommand(subopt) = &command.data.options[0].value else {
-        //unreachable
-        panic!()
-    };
-    let card_name = subopt[0].value.as_str().unwrap();
+    common_quiz: &crate::db::Quiz,
+    input: &str,
+) -> Result<bool, String> {
+    let quiz = get_quiz(&bot.database, &common_quiz.id)
+        .await
+        .map_err(|e| e.to_string())?;
+    let settings = get_settings(&bot.database, common_quiz.guild_id)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(is_correct(&quiz, common_quiz, &settings, input))
+}
 
+pub async fn command_ans(
+    bot: &Bot,
+    ctx: &Context,
+    user: &User,
+    card_name: &str,
+    common_quiz: &crate::db::Quiz,
+) -> Result<(Reply, bool), String> {
     info!("Answered: {}", card_name);
-    let content = match get_quiz(&bot.database, &command.user.id.into()).await {
+    let content = match get_quiz(&bot.database, &common_quiz.id).await {
         Ok(quiz) => {
-            if roughly_card_name_equal(card_name, &quiz.card_name, &quiz.card_name_ruby) {
-                let _ = crate::db::delete_quiz(&bot.database, &command.user.id.into()).await;
-
-                format!("{}の回答：{}\n\n正解！ \n https://www.db.yugioh-card.com/yugiohdb/card_search.action?ope=2&cid={}&request_locale=ja",                        
-                command.user.mention(),
-                card_name,
-                quiz.konami_id
-              )
+            let settings = get_settings(&bot.database, common_quiz.guild_id)
+                .await
+                .map_err(|e| e.to_string())?;
+            if is_correct(&quiz, common_quiz, &settings, card_name) {
+                // 答えは削除する前に取っておく
+                let answer = reveal(&bot.database, &bot.http_client, &common_quiz.id).await?;
+                // 先に正解した人がいれば、このクイズは既に終わっている
+                let Some(quiz) = finish_quiz(
+                    &bot.database,
+                    &common_quiz.id,
+                    QuizOutcome::Correct,
+                    Some(user.id.into()),
+                )
+                .await
+                .map_err(|e| format!("データベースでエラーが発生しました：{}", e))?
+                else {
+                    return Ok((Reply::text(QUIZ_ENDED), false));
+                };
+                disable_buttons(&ctx.http, &quiz).await;
+
+                let answer = answer.with_content(format!(
+                    "{}の回答：{}\n\n正解！{}",
+                    user.mention(),
+                    card_name,
+                    score_message(&quiz)
+                ));
+                return Ok((answer, true));
             } else {
-                format!(
+                let attempted = crate::db::add_attempt(&bot.database, &common_quiz.id)
+                    .await
+                    .map_err(|e| e.to_string())?;
+
+                let reply = Reply::text(format!(
                     "{}の回答：{}\n\n不正解...",
-                    command.user.mention(),
+                    user.mention(),
                     card_name
-                )
+                ));
+                // 穴埋めではカード名を見せているので、イラストは鮮明にしない
+                let reply = if attempted.quiz_mode == QuizMode::Blank {
+                    reply
+                } else {
+                    Reply {
+                        attachments: art_attachment(
+                            &bot.http_client,
+                            &quiz,
+                            attempted.reveal_level(),
+                        )
+                        .await
+                        .into_iter()
+                        .collect(),
+                        ..reply
+                    }
+                };
+                if attempted.quiz_mode == QuizMode::Partial {
+                    reply.with_next_segment(
+                        &attempted,
+                        &segments(quiz.structured_text.as_deref(), &quiz.card_text),
+                        &quiz.card_name,
+                    )?
+                } else {
+                    reply
+                }
             }
         }
-        Err(err) => format!(
+        // 読み込む間に、他の人が先に正解した
+        Err(sqlx::Error::RowNotFound) => Reply::text(QUIZ_ENDED),
+        Err(err) => Reply::text(format!(
             "データベースでエラーが発生しました (`/quiz ygo new` は実行しましたか？) : {}",
             err
-        ),
+        )),
     };
 
-    Ok(content)
+    Ok((content, false))
 }
 
-pub async fn command_giveup(
+pub async fn command_hint(
     bot: &Bot,
     _: &Context,
-    command: &CommandInteraction,
-) -> Result<String, String> {
-    info!("Giveup: {}", command.user);
-    let content = match get_quiz(&bot.database, &command.user.id.into()).await {
+    user: &User,
+    common_quiz: &crate::db::Quiz,
+) -> Result<Reply, String> {
+    info!("Hint: {}", user);
+    let content = match get_quiz(&bot.database, &common_quiz.id).await {
         Ok(quiz) => {
-            let _ = crate::db::delete_quiz(&bot.database, &command.user.id.into()).await;
+            let hinted = crate::db::add_hint(&bot.database, &common_quiz.id)
+                .await
+                .map_err(|e| e.to_string())?;
+            if let Some(answer) = &hinted.blank_answer {
+                return Ok(Reply::text(blank::hint(answer, hinted.reveal_level())));
+            }
+
+            let reply = Reply {
+                attachments: art_attachment(&bot.http_client, &quiz, hinted.reveal_level())
+                    .await
+                    .into_iter()
+                    .collect(),
+                ..Reply::text("ヒント：イラストが少し鮮明になりました")
+            };
+            if hinted.quiz_mode == QuizMode::Partial {
+                reply.with_next_segment(
+                    &hinted,
+                    &segments(quiz.structured_text.as_deref(), &quiz.card_text),
+                    &quiz.card_name,
+                )?
+            } else {
+                reply
+            }
+        }
+        Err(err) => Reply::text(format!(
+            "データベースでエラーが発生しました (`/quiz ygo new` は実行しましたか？) : {}",
+            err
+        )),
+    };
 
-            format!("正解は「{}」（{}）でした \n https://www.db.yugioh-card.com/yugiohdb/card_search.action?ope=2&cid={}&request_locale=ja", quiz.card_name, quiz.card_name_ruby, quiz.konami_id)
+    Ok(content)
+}
+
+pub async fn command_giveup(
+    bot: &Bot,
+    ctx: &Context,
+    user: &User,
+    common_quiz: &crate::db::Quiz,
+) -> Result<Reply, String> {
+    info!("Giveup: {}", user);
+    let content = match reveal(&bot.database, &bot.http_client, &common_quiz.id).await {
+        Ok(content) => {
+            if let Ok(Some(quiz)) = finish_quiz(
+                &bot.database,
+                &common_quiz.id,
+                QuizOutcome::Giveup,
+                Some(user.id.into()),
+            )
+            .await
+            {
+                disable_buttons(&ctx.http, &quiz).await;
+            }
+
+            content
         }
-        Err(err) => format!(
+        Err(err) => Reply::text(format!(
             "データベースでエラーが発生しました (`/quiz new` は実行しましたか？) : {}",
             err
-        ),
+        )),
     };
 
     Ok(content)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CARD_PAGE: &str = r#"<html><body>
+        <div id="cardname"><h1>
+            <span class="ruby">ブルーアイズ・ホワイト・ドラゴン</span>青眼の白龍
+            <span>Blue-Eyes White Dragon</span>
+        </h1></div>
+        <div id="CardSet"><div class="top">
+            <div></div><div></div><div></div>
+            <div class="CardText"><div class="item_box_text"><div class="text_title">カードテキスト</div>高い攻撃力を誇る伝説のドラゴン。</div></div>
+        </div></div>
+    </body></html>"#;
+
+    #[test]
+    fn parse_japanese_card_page() {
+        let Ok((name, ruby, text)) = parse_card_page(CARD_PAGE, "normal") else {
+            panic!("failed to parse the card page");
+        };
+
+        assert_eq!(name, "青眼の白龍");
+        assert_eq!(ruby, "ブルーアイズ・ホワイト・ドラゴン");
+        assert_eq!(
+            text.flavor.as_deref(),
+            Some("高い攻撃力を誇る伝説のドラゴン。")
+        );
+    }
+
+    #[test]
+    fn unusable_only_on_card_pages() {
+        // カードのページだが日本語のテキストがない
+        let without_text = CARD_PAGE.replace("class=\"top\"", "class=\"bottom\"");
+        assert!(matches!(
+            parse_card_page(&without_text, "normal"),
+            Err(DrawError::Unusable(_))
+        ));
+        // メンテナンス中のページなどは、カードのせいではない
+        assert!(matches!(
+            parse_card_page("<html><body>メンテナンス中</body></html>", "normal"),
+            Err(DrawError::Failed(_))
+        ));
+    }
+}
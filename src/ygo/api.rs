@@ -0,0 +1,164 @@
+//! YGOPRODeck の `cardinfo` API の応答
+//! https://ygoprodeck.com/api-guide/
+
+use serde::Deserialize;
+
+/// 検索に一致するカードがない場合などは `data` の代わりに `error` が返る
+#[derive(Deserialize)]
+pub(crate) struct CardInfoResponse {
+    #[serde(default)]
+    pub data: Vec<CardInfo>,
+    pub error: Option<String>,
+}
+
+/// 出題に使わない項目は、形が変わっても解析に失敗しないよう省略可能にしておく
+#[derive(Deserialize)]
+pub(crate) struct CardInfo {
+    pub id: i64,
+    pub name: String,
+    /// 遊戯王カードデータベースのページで、テキストの読み方を決める
+    #[serde(rename = "frameType")]
+    pub frame_type: String,
+    /// 収録されたパック。出題には使わないが、応答の形として読んでおく
+    #[allow(dead_code)]
+    #[serde(default)]
+    pub card_sets: Vec<CardSet>,
+    /// どの禁止制限にも載っていなければ存在しない
+    #[allow(dead_code)]
+    pub banlist_info: Option<BanlistInfo>,
+    #[serde(default)]
+    pub card_images: Vec<CardImage>,
+    /// `misc=yes` を指定した場合だけ返る
+    #[serde(default)]
+    pub misc_info: Vec<MiscInfo>,
+}
+
+#[allow(dead_code)]
+#[derive(Deserialize)]
+pub(crate) struct CardSet {
+    pub set_name: Option<String>,
+    pub set_code: Option<String>,
+    pub set_rarity: Option<String>,
+}
+
+#[allow(dead_code)]
+#[derive(Deserialize)]
+pub(crate) struct BanlistInfo {
+    pub ban_tcg: Option<String>,
+    pub ban_ocg: Option<String>,
+    pub ban_goat: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct CardImage {
+    pub image_url_cropped: String,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct MiscInfo {
+    /// 遊戯王カードデータベースでのカード ID。登録前のカードにはない
+    pub konami_id: Option<i64>,
+}
+
+/// `cardinfo` の応答を解析し、カードの一覧を返す
+pub(crate) fn parse_cardinfo(body: &str) -> Result<Vec<CardInfo>, String> {
+    let response: CardInfoResponse = serde_json::from_str(body)
+        .map_err(|e| format!("YGOPRODeck の応答を解析できません：{}", e))?;
+    if let Some(error) = response.error {
+        return Err(format!("YGOPRODeck でエラーが発生しました：{}", error));
+    }
+
+    Ok(response.data)
+}
+
+impl CardInfo {
+    pub fn konami_id(&self) -> Option<i64> {
+        self.misc_info.iter().find_map(|misc| misc.konami_id)
+    }
+
+    /// イラスト部分だけを切り抜いた画像
+    pub fn cropped_image_url(&self) -> Option<&str> {
+        self.card_images
+            .first()
+            .map(|image| image.image_url_cropped.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_normal_monster() {
+        let cards = parse_cardinfo(include_str!(
+            "../../fixtures/ygoprodeck/normal_monster.json"
+        ))
+        .unwrap();
+        assert_eq!(cards.len(), 1);
+
+        let card = &cards[0];
+        assert_eq!(card.name, "Dark Magician");
+        assert_eq!(card.frame_type, "normal");
+        assert_eq!(card.card_sets.len(), 2);
+        assert!(card.banlist_info.is_none());
+        assert_eq!(card.konami_id(), Some(4041));
+        assert_eq!(
+            card.cropped_image_url(),
+            Some("https://images.ygoprodeck.com/images/cards_cropped/46986414.jpg")
+        );
+    }
+
+    #[test]
+    fn parse_banned_spell() {
+        let cards =
+            parse_cardinfo(include_str!("../../fixtures/ygoprodeck/banned_spell.json")).unwrap();
+
+        let card = &cards[0];
+        assert_eq!(card.frame_type, "spell");
+        let banlist = card.banlist_info.as_ref().unwrap();
+        assert_eq!(banlist.ban_tcg.as_deref(), Some("Banned"));
+        assert_eq!(banlist.ban_ocg.as_deref(), Some("Banned"));
+        assert_eq!(banlist.ban_goat, None);
+        assert_eq!(card.konami_id(), Some(4844));
+        assert_eq!(
+            card.cropped_image_url(),
+            Some("https://images.ygoprodeck.com/images/cards_cropped/55144522.jpg")
+        );
+    }
+
+    #[test]
+    fn parse_without_misc_info() {
+        let cards =
+            parse_cardinfo(include_str!("../../fixtures/ygoprodeck/without_misc.json")).unwrap();
+
+        assert!(cards[0].misc_info.is_empty());
+        assert_eq!(cards[0].konami_id(), None);
+    }
+
+    #[test]
+    fn parse_error() {
+        let err = parse_cardinfo(include_str!("../../fixtures/ygoprodeck/error.json"))
+            .map(|_| ())
+            .unwrap_err();
+        assert!(err.contains("No card matching your query"), "{}", err);
+    }
+
+    #[test]
+    fn tolerate_changes_in_unused_fields() {
+        let cards = parse_cardinfo(
+            r#"{"data":[{"id":1,"name":"x","frameType":"normal","card_sets":[{"set_name":"A"}],"banlist_info":{}}]}"#,
+        )
+        .unwrap();
+
+        assert_eq!(cards[0].card_sets[0].set_rarity, None);
+        assert_eq!(cards[0].banlist_info.as_ref().unwrap().ban_tcg, None);
+    }
+
+    #[test]
+    fn report_schema_mismatch() {
+        let err = parse_cardinfo(r#"{"data":[{"id":1,"name":"x"}]}"#)
+            .map(|_| ())
+            .unwrap_err();
+        assert!(err.contains("missing field `frameType`"), "{}", err);
+    }
+}
@@ -1,64 +1,80 @@
-use sqlx::{FromRow, PgConnection, PgPool};
+use sqlx::{types::Json, FromRow, PgConnection, PgPool};
 
-use crate::db::insert_quiz;
+use crate::db::{insert_quiz, QuizParams};
+use crate::ygo::text::CardText;
+use crate::ygo::Card;
 
+#[allow(dead_code)]
 #[derive(FromRow)]
 pub struct Quiz {
-    pub user_id: i64,
+    pub quiz_id: i64,
     pub konami_id: i64,
     pub card_name: String,
     pub card_name_ruby: String,
     pub card_text: String,
+    pub image_url: String,
+    pub structured_text: Option<Json<CardText>>,
+    /// 出題時に取得したイラスト
+    pub art: Option<Vec<u8>>,
 }
 
+/// 今日の一枚に既に挑戦していれば `None` を返す
 pub(crate) async fn new_quiz(
     pool: &PgPool,
-    user_id: &i64,
-    konami_id: &i64,
-    card_name: &String,
-    card_name_ruby: &String,
-    card_text: &String,
-) -> Result<String, sqlx::Error> {
+    params: &QuizParams,
+    card: &Card,
+    art: &[u8],
+    segments: Option<i32>,
+    blank_answer: Option<&str>,
+) -> Result<Option<(crate::db::Quiz, Vec<crate::db::Quiz>)>, sqlx::Error> {
     let mut tx = pool.begin().await?;
 
-    crate::db::delete_tx_quiz(&mut tx, user_id).await?;
+    let Some((quiz, replaced)) = insert_quiz(
+        &mut tx,
+        params,
+        &crate::db::QuizType::Ygo,
+        segments,
+        blank_answer,
+    )
+    .await?
+    else {
+        return Ok(None);
+    };
 
     sqlx::query(
         r#"
-      INSERT INTO ygo_quiz (user_id, konami_id, card_name, card_name_ruby, card_text)
-      VALUES ($1, $2, $3, $4, $5)
+      INSERT INTO ygo_quiz (quiz_id, konami_id, card_name, card_name_ruby, card_text, image_url, structured_text, art)
+      VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
     "#,
     )
-    .bind(&user_id)
-    .bind(&konami_id)
-    .bind(&card_name)
-    .bind(&card_name_ruby)
-    .bind(&card_text)
+    .bind(quiz.id)
+    .bind(card.konami_id)
+    .bind(&card.card_name)
+    .bind(&card.card_name_ruby)
+    .bind(&card.card_text)
+    .bind(&card.image_url)
+    .bind(Json(&card.text))
+    .bind(art)
     .execute(&mut *tx)
     .await?;
 
-    insert_quiz(&mut *tx, user_id, &crate::db::QuizType::Ygo).await?;
-
     tx.commit().await?;
 
-    Ok(format!(
-        "Start quiz about `{}` for `{}`",
-        card_name, user_id
-    ))
+    Ok(Some((quiz, replaced)))
 }
 
-pub(crate) async fn get_quiz(pool: &PgPool, user_id: &i64) -> Result<Quiz, sqlx::Error> {
-    let data: Quiz = sqlx::query_as(r#"SELECT * FROM ygo_quiz WHERE user_id = $1"#)
-        .bind(user_id)
+pub(crate) async fn get_quiz(pool: &PgPool, quiz_id: &i64) -> Result<Quiz, sqlx::Error> {
+    let data: Quiz = sqlx::query_as(r#"SELECT * FROM ygo_quiz WHERE quiz_id = $1"#)
+        .bind(quiz_id)
         .fetch_one(pool)
         .await?;
 
     Ok(data)
 }
 
-pub(crate) async fn delete_quiz(pool: &mut PgConnection, user_id: &i64) -> Result<(), sqlx::Error> {
-    sqlx::query(r#"DELETE FROM ygo_quiz WHERE user_id = $1"#)
-        .bind(user_id)
+pub(crate) async fn delete_quiz(pool: &mut PgConnection, quiz_id: &i64) -> Result<(), sqlx::Error> {
+    sqlx::query(r#"DELETE FROM ygo_quiz WHERE quiz_id = $1"#)
+        .bind(quiz_id)
         .execute(pool)
         .await?;
 
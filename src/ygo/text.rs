@@ -0,0 +1,294 @@
+//! 遊戯王カードデータベースのカードテキストを、素材・効果ごとに分けたもの
+
+use std::fmt;
+
+use scraper::{ElementRef, Node};
+use serde::{Deserialize, Serialize};
+
+use crate::common::{partial_segments, split_sentences};
+
+/// 素材の指定を持つモンスターの `frameType`
+const MATERIAL_FRAMES: [&str; 4] = ["fusion", "synchro", "xyz", "link"];
+
+/// 前の効果の続きとして扱う行の頭。選択肢の列挙などに使われる
+const CONTINUATION_MARKS: [char; 2] = ['●', '・'];
+
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Debug)]
+pub(crate) struct CardText {
+    /// 融合・シンクロ・エクシーズ・リンクモンスターの素材の指定
+    pub materials: Option<String>,
+    /// ①②③… の効果ごとに分けたテキスト。番号のない文はそれぞれ 1 つとして扱う
+    pub effects: Vec<String>,
+    pub pendulum_effects: Vec<String>,
+    /// 通常モンスターのフレーバーテキスト。このときは `effects` は空
+    pub flavor: Option<String>,
+}
+
+impl CardText {
+    /// `frame_type` は YGOPRODeck の `frameType`（`effect`、`fusion_pendulum` など）
+    /// ペンデュラムモンスターでなければ `pendulum_text` は空
+    pub fn parse(frame_type: &str, text: &str, pendulum_text: &str) -> CardText {
+        let mut lines = non_empty_lines(text).peekable();
+
+        let frame = frame_type.trim_end_matches("_pendulum");
+        let has_materials = MATERIAL_FRAMES.contains(&frame);
+        let materials = lines
+            .next_if(|line| has_materials && !starts_with_number(line))
+            .map(|line| line.to_owned());
+
+        let (effects, flavor) = if frame == "normal" {
+            (
+                vec![],
+                Some(lines.collect::<Vec<_>>().join("\n")).filter(|t| !t.is_empty()),
+            )
+        } else {
+            (segment(lines), None)
+        };
+
+        CardText {
+            materials,
+            effects,
+            pendulum_effects: segment(non_empty_lines(pendulum_text)),
+            flavor,
+        }
+    }
+
+    /// テキストを少しずつ見せる出題での区切り。素材、効果、ペンデュラム効果の順に見せる
+    /// フレーバーテキストは文ごとに分ける
+    pub fn segments(&self) -> Vec<String> {
+        let mut segments = self
+            .materials
+            .iter()
+            .chain(&self.effects)
+            .cloned()
+            .collect::<Vec<_>>();
+        segments.extend(
+            self.pendulum_effects
+                .iter()
+                .map(|effect| format!("【ペンデュラム効果】{}", effect)),
+        );
+        segments.extend(
+            self.flavor
+                .iter()
+                .flat_map(|flavor| split_sentences(flavor)),
+        );
+
+        partial_segments(segments)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.materials.is_none()
+            && self.effects.is_empty()
+            && self.pendulum_effects.is_empty()
+            && self.flavor.is_none()
+    }
+}
+
+/// 表示用の全文
+impl fmt::Display for CardText {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut sections = vec![];
+        if !self.pendulum_effects.is_empty() {
+            sections.push(format!(
+                "【ペンデュラム効果】\n{}",
+                self.pendulum_effects.join("\n")
+            ));
+        }
+
+        let monster_text = self
+            .materials
+            .iter()
+            .chain(&self.effects)
+            .chain(&self.flavor)
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join("\n");
+        match (sections.is_empty(), monster_text.is_empty()) {
+            (_, true) => {}
+            (true, false) => sections.push(monster_text),
+            (false, false) => sections.push(format!("【モンスター効果】\n{}", monster_text)),
+        }
+
+        write!(f, "{}", sections.join("\n\n"))
+    }
+}
+
+/// テキスト欄の要素から文字だけを取り出す
+/// 見出し (`div.text_title`) は除き、`<br>` は改行にする。文字参照は解析時に元に戻っている
+pub(crate) fn extract_text(element: ElementRef) -> String {
+    let mut text = String::new();
+    for node in element.descendants() {
+        let in_title = node
+            .ancestors()
+            .take_while(|ancestor| ancestor.id() != element.id())
+            .filter_map(ElementRef::wrap)
+            .any(|ancestor| ancestor.value().classes().any(|c| c == "text_title"));
+        match node.value() {
+            Node::Text(t) if !in_title => text.push_str(t),
+            Node::Element(e) if e.name() == "br" => text.push('\n'),
+            _ => {}
+        }
+    }
+
+    non_empty_lines(&text).collect::<Vec<_>>().join("\n")
+}
+
+fn non_empty_lines(text: &str) -> impl Iterator<Item = &str> {
+    text.lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty())
+}
+
+/// ①〜⑳ で始まるか
+fn starts_with_number(line: &str) -> bool {
+    line.starts_with(|c| ('\u{2460}'..='\u{2473}').contains(&c))
+}
+
+/// 行を効果ごとにまとめる。選択肢の列挙などは前の効果の続きとする
+fn segment<'a>(lines: impl Iterator<Item = &'a str>) -> Vec<String> {
+    let mut effects: Vec<String> = vec![];
+    for line in lines {
+        match effects.last_mut() {
+            Some(effect) if line.starts_with(CONTINUATION_MARKS) => {
+                effect.push('\n');
+                effect.push_str(line);
+            }
+            _ => effects.push(line.to_owned()),
+        }
+    }
+
+    effects
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use scraper::{Html, Selector};
+
+    fn extract(html: &str) -> String {
+        let document = Html::parse_fragment(html);
+        let selector = Selector::parse("div.item_box_text").unwrap();
+        extract_text(document.select(&selector).next().unwrap())
+    }
+
+    #[test]
+    fn decode_entities_and_skip_title() {
+        let text = extract(
+            r#"<div class="item_box_text">
+                <div class="text_title">
+                    カードテキスト
+                </div>
+                ①：「A&amp;B」を手札に加える。<br>
+                ②：<span>相手の</span>モンスター１体を対象として発動できる。&lt;注意&gt;
+            </div>"#,
+        );
+
+        assert_eq!(
+            text,
+            "①：「A&B」を手札に加える。\n②：相手のモンスター１体を対象として発動できる。<注意>"
+        );
+    }
+
+    #[test]
+    fn split_effects() {
+        let text = CardText::parse(
+            "effect",
+            "このカード名の①②の効果はそれぞれ１ターンに１度しか使用できない。\n\
+             ①：このカードが召喚に成功した場合に発動できる。\n\
+             ②：以下の効果から１つを選択して発動できる。\n\
+             ●デッキから１枚ドローする。\n\
+             ●手札を１枚捨てる。",
+            "",
+        );
+
+        assert_eq!(text.materials, None);
+        assert_eq!(text.effects.len(), 3);
+        assert!(text.effects[0].starts_with("このカード名の"));
+        assert_eq!(
+            text.effects[2],
+            "②：以下の効果から１つを選択して発動できる。\n●デッキから１枚ドローする。\n●手札を１枚捨てる。"
+        );
+        assert_eq!(text.flavor, None);
+    }
+
+    #[test]
+    fn separate_materials() {
+        let text = CardText::parse(
+            "fusion",
+            "「青眼の白龍」＋「青眼の白龍」＋「青眼の白龍」\n①：このカードは相手の効果の対象にならない。",
+            "",
+        );
+
+        assert_eq!(
+            text.materials.as_deref(),
+            Some("「青眼の白龍」＋「青眼の白龍」＋「青眼の白龍」")
+        );
+        assert_eq!(
+            text.effects,
+            vec!["①：このカードは相手の効果の対象にならない。"]
+        );
+    }
+
+    #[test]
+    fn keep_flavor_of_normal_monsters() {
+        let text = CardText::parse(
+            "normal",
+            "魔法使いとしては、攻撃力・守備力ともに最高クラス。",
+            "",
+        );
+
+        assert!(text.effects.is_empty());
+        assert_eq!(
+            text.flavor.as_deref(),
+            Some("魔法使いとしては、攻撃力・守備力ともに最高クラス。")
+        );
+    }
+
+    #[test]
+    fn display_pendulum_sections() {
+        let text = CardText::parse(
+            "effect_pendulum",
+            "①：このカードの攻撃力は５００アップする。",
+            "①：自分のＰモンスターの攻撃力は３００アップする。",
+        );
+
+        assert_eq!(
+            text.to_string(),
+            "【ペンデュラム効果】\n①：自分のＰモンスターの攻撃力は３００アップする。\n\n\
+             【モンスター効果】\n①：このカードの攻撃力は５００アップする。"
+        );
+    }
+
+    #[test]
+    fn segments_in_reveal_order() {
+        let text = CardText::parse(
+            "synchro_pendulum",
+            "チューナー＋チューナー以外のモンスター１体以上\n①：効果Ａ。\n②：効果Ｂ。",
+            "①：Ｐ効果。",
+        );
+        assert_eq!(
+            text.segments(),
+            vec![
+                "チューナー＋チューナー以外のモンスター１体以上",
+                "①：効果Ａ。",
+                "②：効果Ｂ。",
+                "【ペンデュラム効果】①：Ｐ効果。"
+            ]
+        );
+
+        let text = CardText::parse("normal", "古代の竜。その力は計り知れない。", "");
+        assert_eq!(
+            text.segments(),
+            vec!["古代の竜。", "その力は計り知れない。"]
+        );
+    }
+
+    #[test]
+    fn display_plain_text() {
+        let text = CardText::parse("spell", "①：カードを２枚ドローする。", "");
+
+        assert_eq!(text.to_string(), "①：カードを２枚ドローする。");
+        assert!(CardText::parse("spell", "", "").is_empty());
+    }
+}